@@ -303,6 +303,8 @@ fn main() {
         .allowlist_var("PHP_OUTPUT_HANDLER_STDFLAGS")
         .allowlist_type("zval")
         .allowlist_var("ZTS")
+        .allowlist_var("SAPI_HEADER_SENT_SUCCESSFULLY")
+        .allowlist_var("SAPI_HEADER_DO_SEND")
         .allowlist_type("ZTS")
         .allowlist_type("zend_constant")
         .allowlist_type("zend_fcall_info")
@@ -326,6 +328,7 @@ fn main() {
         .allowlist_function("zend_hash_move_forward_ex")
         .allowlist_function("zend_eval_string_ex")
         .allowlist_function("php_rust_init")
+        .allowlist_type("php_rust_ini")
         .allowlist_function("php_request_shutdown")
         .allowlist_function("php_module_shutdown")
         .allowlist_function("sapi_shutdown")
@@ -346,14 +349,72 @@ fn main() {
         .allowlist_function("php_rust_clear_server_context")
         .allowlist_function("php_rust_set_tmp_server_ctx")
         .allowlist_function("php_rust_set_server_context")
+        .allowlist_function("php_rust_get_server_context")
+        .allowlist_function("php_rust_thread_attach")
+        .allowlist_function("php_rust_thread_detach")
         .allowlist_function("php_register_variable_safe")
         .allowlist_function("libphp_zval_addref_p")
         .allowlist_function("libphp_zval_delref_p")
+        .allowlist_function("libphp_zval_get_stringl")
+        .allowlist_function("libphp_output_capture_start")
+        .allowlist_function("libphp_output_capture_end")
+        .allowlist_function("libphp_output_start_sink")
         .allowlist_function("libphp_eval_stringl_ex")
+        .allowlist_function("libphp_eval_stringl_scoped_ex")
+        .allowlist_function("libphp_call_function")
+        .allowlist_function("libphp_exit_status")
+        .allowlist_function("libphp_exit_watch")
+        .allowlist_function("libphp_exited")
+        .allowlist_function("libphp_error_reporting")
+        .allowlist_function("libphp_clear_last_error")
+        .allowlist_function("libphp_last_error")
+        .allowlist_function("libphp_set_time_limit")
+        .allowlist_function("libphp_set_memory_limit")
+        .allowlist_function("libphp_restore_memory_limit")
+        .allowlist_function("libphp_vm_flags")
+        .allowlist_function("libphp_vm_raise_timeout")
+        .allowlist_function("libphp_vm_clear_timeout")
+        .allowlist_function("libphp_install_vm_hooks")
+        .allowlist_function("libphp_last_fatal_reason")
+        .allowlist_type("libphp_fatal_reason")
+        .allowlist_function("libphp_vm_raise_interrupt")
+        .allowlist_function("libphp_vm_clear_interrupt")
+        .allowlist_type("libphp_vm_flags")
+        .allowlist_function("libphp_ini_get")
+        .allowlist_function("libphp_ini_set")
+        .allowlist_function("libphp_hash_str_update")
+        .allowlist_function("libphp_hash_index_update")
+        .allowlist_function("libphp_zval_create_null")
+        .allowlist_function("libphp_zval_create_bool")
+        .allowlist_function("libphp_zval_create_double")
+        .allowlist_function("libphp_zval_create_stringl")
+        .allowlist_function("libphp_zval_create_array")
+        .allowlist_function("zend_array_destroy")
         .allowlist_function("libphp_execute_simple_script")
+        .allowlist_function("libphp_execute_source")
+        .allowlist_function("libphp_compile_file")
+        .allowlist_function("libphp_compile_source")
+        .allowlist_function("libphp_execute_op_array")
+        .allowlist_function("libphp_destroy_op_array")
+        .allowlist_function("libphp_lint_file")
+        .allowlist_function("libphp_lint_source")
+        .allowlist_function("php_rust_set_error_hook")
+        .allowlist_type("php_rust_error_hook")
+        .allowlist_type("php_rust_error_action")
+        .allowlist_function("libphp_symbol_table")
+        .allowlist_function("libphp_zval_copy_deref")
+        .allowlist_function("libphp_global_get")
+        .allowlist_function("libphp_global_set")
+        .allowlist_function("libphp_global_unset")
         .allowlist_function("zend_hash_add")
         .allowlist_function("zend_hash_next_index_insert")
         .allowlist_function("php_rust_get_request_info")
+        .allowlist_function("php_rust_set_argv")
+        .allowlist_function("php_rust_script_path")
+        .allowlist_function("php_rust_set_request_info")
+        .allowlist_type("php_rust_request_info")
+        .allowlist_function("php_rust_cli_request_startup")
+        .allowlist_type("php_rust_stdio")
         .allowlist_function("php_request_startup")
         .allowlist_type("zend_function_entry")
         .header("src/wrapper.h")
@@ -0,0 +1,15 @@
+use libphp::exec::Context;
+
+fn main() {
+    let mut context = Context::builder()
+        .argv(["artisan", "migrate", "--force"])
+        .build();
+
+    let summary = context
+        .result_of(
+            "json_encode(['argc' => $argc, 'argv' => $argv, 'server' => $_SERVER['argv']])",
+            false,
+        )
+        .unwrap();
+    println!("{}", summary);
+}
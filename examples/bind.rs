@@ -7,6 +7,6 @@ fn main() {
         ctx.bind("myVar", "Hello, this variable is defined in Rust!");
     });
 
-    let my_var = context.result_of("$myVar", false);
+    let my_var = context.result_of("$myVar", false).unwrap();
     println!("my_var = {:?}", my_var);
 }
@@ -1,16 +1,26 @@
-use libphp::exec::Context;
+use libphp::{exec::Context, value::Value};
 
 fn main() {
     let mut context = Context::new();
 
-    let version_result = context.call("phpversion");
+    let version_result = context.call("phpversion").unwrap();
     dbg!(version_result);
 
-    let strlen_result = context.call_with("strlen", &["Hello, world!"]);
+    let strlen_result = context.call_with("strlen", &["Hello, world!"]).unwrap();
     dbg!(strlen_result);
 
-    context.execute_file("./examples/scripts/functions.php", false);
+    let str_pad_result = context
+        .call_with(
+            "str_pad",
+            &[Value::from("7"), Value::from(3), Value::from("0")],
+        )
+        .unwrap();
+    dbg!(str_pad_result);
 
-    let fib_30_result = context.call_with("fib", &[35]);
+    context
+        .execute_file("./examples/scripts/functions.php", false)
+        .unwrap();
+
+    let fib_30_result = context.call_with("fib", &[35]).unwrap();
     dbg!(fib_30_result);
-}
\ No newline at end of file
+}
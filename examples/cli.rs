@@ -0,0 +1,54 @@
+use std::{
+    io::{Cursor, Write},
+    sync::{Arc, Mutex},
+};
+
+use libphp::{
+    exec::ContextBuilder,
+    sapi::cli::{CliSapi, CliStdio},
+};
+
+// Collects what PHP writes, so it can be inspected afterwards.
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+const SCRIPT: &str = r#"<?php
+$name = trim(fgets(STDIN));
+echo PHP_SAPI, ': Hello, ', $name, "!\n";
+fwrite(STDERR, 'Arguments: ' . implode(' ', array_slice($argv, 1)) . "\n");
+file_put_contents('php://stdout', "Written to php://stdout\n");
+exit(3);
+"#;
+
+fn main() {
+    let stdout = Buffer::default();
+    let stderr = Buffer::default();
+
+    let stdio = CliStdio::new(Cursor::new("Ferris\n"), stdout.clone(), stderr.clone());
+    let mut context = ContextBuilder::<CliSapi>::with_sapi(Box::new(stdio))
+        .argv(["greet.php", "--loud"])
+        .build();
+
+    let result = context.execute_source(SCRIPT, "greet.php");
+
+    println!("Result: {:?}", result.map(|value| value.to_int()));
+    println!("Exit status: {}", context.exit_status());
+    println!(
+        "stdout: {:?}",
+        String::from_utf8_lossy(&stdout.0.lock().unwrap())
+    );
+    println!(
+        "stderr: {:?}",
+        String::from_utf8_lossy(&stderr.0.lock().unwrap())
+    );
+}
@@ -0,0 +1,26 @@
+use std::time::Instant;
+
+use libphp::exec::Context;
+
+const TEMPLATE: &str = r#"<?php
+return sprintf('<li>%s</li>', htmlspecialchars($GLOBALS['item']));
+"#;
+
+fn main() {
+    let mut context = Context::new();
+
+    let script = context
+        .compile_source(TEMPLATE, "/virtual/templates/item.php")
+        .expect("template should compile");
+
+    let start = Instant::now();
+    let mut last = None;
+
+    for i in 0..10_000 {
+        context.set_global("item", format!("Item #{i} <b>").as_str());
+        last = Some(script.run(&mut context).unwrap());
+    }
+
+    println!("Rendered 10000 items in {:?}", start.elapsed());
+    println!("Last item: {:?}", last.unwrap());
+}
@@ -0,0 +1,20 @@
+use libphp::exec::Engine;
+
+fn main() {
+    let engine = Engine::boot();
+
+    for job in 1..=3 {
+        let result = engine.request(move |ctx| {
+            ctx.bind("job", job);
+            ctx.execute_source(
+                "<?php\nfunction handle($job) { return $job * 2; }\nreturn handle($job);",
+                "job.php",
+            )
+            .unwrap()
+            .to_int()
+        });
+
+        // Each request starts from scratch, so `handle` can be declared again.
+        println!("Job {} returned {}", job, result);
+    }
+}
@@ -0,0 +1,43 @@
+use std::{cell::RefCell, rc::Rc};
+
+use libphp::{
+    diagnostic::{ErrorAction, ErrorLevel, PhpDiagnostic},
+    exec::Context,
+};
+
+fn main() {
+    let collected = Rc::new(RefCell::new(Vec::<PhpDiagnostic>::new()));
+
+    let mut context = Context::builder()
+        .error_handler(ErrorLevel::ALL, {
+            let collected = collected.clone();
+
+            move |diagnostic: &PhpDiagnostic| {
+                collected.borrow_mut().push(diagnostic.clone());
+                ErrorAction::Handled
+            }
+        })
+        .build();
+
+    // Nothing is printed for the warning, and @ still silences it.
+    context
+        .execute_source(
+            "<?php echo $missing, @$silenced, 'Done', PHP_EOL;",
+            "errors.php",
+        )
+        .unwrap();
+
+    for diagnostic in collected.borrow().iter() {
+        println!("Collected: {}", diagnostic);
+    }
+
+    // Turn warnings into errors, and let everything else through.
+    context.set_error_handler(ErrorLevel::WARNING, |_: &PhpDiagnostic| {
+        ErrorAction::Escalate
+    });
+
+    match context.result_of("$undefined + 1", false) {
+        Ok(value) => println!("Result: {:?}", value.to_int()),
+        Err(err) => println!("Escalated: {}", err),
+    }
+}
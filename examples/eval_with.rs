@@ -0,0 +1,27 @@
+use libphp::{exec::Context, value::Value};
+
+fn main() {
+    let mut context = Context::new();
+
+    let greeting = context
+        .eval_with(
+            "\"Hello, $name! You are $age years old.\"",
+            [("name", Value::from("Ferris")), ("age", Value::from(8))],
+        )
+        .unwrap();
+    println!("greeting = {greeting:?}");
+
+    let (result, locals) = context
+        .eval_with_locals("$total = $a + $b", [("a", 20), ("b", 22)])
+        .unwrap();
+    println!("result = {result:?}");
+
+    for (name, value) in locals {
+        println!("${name} = {value:?}");
+    }
+
+    println!(
+        "$total leaked into globals: {}",
+        context.global("total").is_some()
+    );
+}
@@ -0,0 +1,24 @@
+use libphp::{error::PhpError, exec::Context};
+
+fn main() {
+    let mut context = Context::new();
+
+    match context.execute_source("echo 'Bye!', PHP_EOL; exit(42);", "exit.php") {
+        Err(PhpError::Exit(status)) => println!("The script exited with status {}", status),
+        other => println!("Unexpected result: {:?}", other.map(|value| value.to_int())),
+    }
+
+    // die() without a status exits with 0, and this process keeps running.
+    println!(
+        "{:?}",
+        context
+            .result_of("die()", false)
+            .map(|value| value.to_int())
+    );
+    println!(
+        "{:?}",
+        context
+            .result_of("1 + 1", false)
+            .map(|value| value.to_int())
+    );
+}
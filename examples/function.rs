@@ -10,7 +10,7 @@ fn main() {
         ctx.define_function("hello_world", hello_world);
     });
 
-    context.result_of("hello_world()", false);
+    context.result_of("hello_world()", false).unwrap();
 }
 
 unsafe extern "C" fn hello_world(execute_data: *mut zend_execute_data, retval: *mut zval) {
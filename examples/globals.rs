@@ -0,0 +1,18 @@
+use libphp::exec::Context;
+
+fn main() {
+    let mut context = Context::new();
+
+    context.set_global("greeting", "Hello from Rust");
+    context
+        .result_of("($GLOBALS['counter'] = 41) + 1", false)
+        .unwrap();
+
+    println!("counter = {:?}", context.global("counter"));
+    println!("unset greeting: {}", context.unset_global("greeting"));
+    println!("greeting = {:?}", context.global("greeting"));
+
+    for (name, value) in context.globals() {
+        println!("${name} is a {}", value.get_type_name());
+    }
+}
@@ -0,0 +1,42 @@
+use libphp::{
+    exec::Context,
+    sapi::http::{HttpExchange, HttpRequest, HttpResponse, HttpSapi},
+};
+
+fn print_response(response: &HttpResponse) {
+    println!("{}", response.status);
+
+    for (name, value) in &response.headers {
+        println!("{}: {}", name, value);
+    }
+
+    println!("\n{}", String::from_utf8_lossy(&response.body));
+}
+
+fn main() {
+    let root = format!("{}/examples/scripts", env!("CARGO_MANIFEST_DIR"));
+
+    let mut context = Context::<HttpSapi>::new_with_sapi(Box::new(HttpExchange::new(
+        HttpRequest::new("GET", "/"),
+    )));
+
+    let response = context
+        .handle_request(
+            HttpRequest::new("POST", "/http.php?page=2")
+                .script(&root, "/http.php")
+                .header("Host", "example.com")
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("name=World"),
+        )
+        .unwrap();
+    print_response(&response);
+
+    let response = context
+        .handle_request(
+            HttpRequest::new("GET", "/?page=2")
+                .script(&root, "/http.php")
+                .header("Cookie", "name=World"),
+        )
+        .unwrap();
+    print_response(&response);
+}
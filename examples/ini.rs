@@ -0,0 +1,25 @@
+use libphp::exec::Context;
+
+fn main() {
+    let mut context = Context::builder()
+        .php_ini_ignore()
+        .ini_defaults([("precision", "10")])
+        .ini("memory_limit", "256M")
+        .on_init(|ctx| println!("display_errors = {:?}", ctx.ini_get("display_errors")))
+        .build();
+
+    println!("memory_limit = {:?}", context.ini_get("memory_limit"));
+    println!("precision = {:?}", context.ini_get("precision"));
+
+    // Runtime changes last until the end of the request.
+    context.ini_set("precision", "4");
+    println!(
+        "{:?}",
+        context
+            .result_of("(string) M_PI", false)
+            .unwrap()
+            .to_string()
+    );
+
+    println!("no_such_setting = {:?}", context.ini_get("no_such_setting"));
+}
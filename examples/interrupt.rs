@@ -0,0 +1,27 @@
+use std::{thread, time::Duration};
+
+use libphp::exec::Context;
+
+fn main() {
+    let mut context = Context::new();
+    let handle = context.interrupt_handle();
+
+    // Stop the script from another thread, like when a client disconnects.
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        handle.interrupt();
+    });
+
+    let result = context.result_of("while (true) {}", false);
+    println!("Endless loop: {:?}", result.map(|value| value.to_int()));
+
+    canceller.join().unwrap();
+
+    // The interrupt ended the request, so this runs in a new one.
+    println!(
+        "{:?}",
+        context
+            .result_of("1 + 1", false)
+            .map(|value| value.to_int())
+    );
+}
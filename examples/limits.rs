@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use libphp::{
+    error::PhpError,
+    exec::{Context, Limits},
+};
+
+fn main() {
+    let mut context = Context::new();
+
+    let limits = Limits {
+        wall_time: Some(Duration::from_millis(500)),
+        memory: Some(32 * 1024 * 1024),
+        ..Default::default()
+    };
+
+    let result = context.with_limits(limits, |ctx| ctx.result_of("while (true) {}", false));
+    println!("Endless loop: {:?}", result.map(|value| value.to_int()));
+
+    let result = context.with_limits(limits, |ctx| {
+        ctx.result_of("str_repeat('x', 64 * 1024 * 1024)", false)
+    });
+    println!("Huge string: {:?}", result.map(|value| value.to_int()));
+
+    // Both ended the request, so this runs in a new one without limits.
+    let value = context
+        .result_of("1 + 1", false)
+        .map(|value| value.to_int());
+    assert_eq!(value, Ok(2));
+    println!("Afterwards: {:?}", value);
+
+    match context.result_of("undefined_function()", false) {
+        Err(PhpError::Fatal(diagnostic)) => println!("Fatal error: {}", diagnostic.message),
+        other => println!("Unexpected result: {:?}", other.map(|value| value.to_int())),
+    }
+}
@@ -7,5 +7,7 @@ fn main() {
         ctx.define("EXAMPLE_CONSTANT_FROM_RUST", "Hello, world!");
     });
 
-    dbg!(context.result_of("EXAMPLE_CONSTANT_FROM_RUST", false));
+    dbg!(context
+        .result_of("EXAMPLE_CONSTANT_FROM_RUST", false)
+        .unwrap());
 }
@@ -0,0 +1,27 @@
+use std::fs::File;
+
+use libphp::exec::Context;
+
+fn main() {
+    let mut context = Context::new();
+
+    let (value, output) = context.capture_output(|ctx| {
+        ctx.result_of("print('Hello from PHP!')", false).unwrap();
+
+        let (_, inner) = ctx.capture_output(|ctx| ctx.result_of("print('nested')", false).unwrap());
+        println!("Inner capture: {:?}", String::from_utf8_lossy(&inner));
+
+        ctx.result_of("strlen('Hello from PHP!')", false).unwrap()
+    });
+
+    println!("Return value: {:?}", value);
+    println!("Captured output: {:?}", String::from_utf8_lossy(&output));
+
+    let path = std::env::temp_dir().join("php-output.log");
+    let log = File::create(&path).expect("Failed to create output log");
+    context.set_output_sink(log);
+    context
+        .execute_file("./examples/scripts/fib.php", false)
+        .unwrap();
+    println!("fib.php output was written to {}", path.display());
+}
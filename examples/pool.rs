@@ -0,0 +1,57 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+    time::Duration,
+};
+
+use libphp::{exec::PhpPool, value::OwnedValue};
+
+// Any async runtime works; this minimal executor keeps the example free of dependencies.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+fn main() {
+    let pool = PhpPool::new(4);
+
+    let jobs = (1..=8)
+        .map(|n| {
+            pool.call(
+                "str_repeat",
+                vec![OwnedValue::from("ab"), OwnedValue::Int(n)],
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for job in jobs {
+        println!(
+            "{:?}",
+            block_on(job).map(|result| result.map(|value| value.as_str().map(String::from)))
+        );
+    }
+
+    let sleepy = pool
+        .run(|ctx| ctx.result_of("sleep(2)", false).map(|value| value.to_int()))
+        .timeout(Duration::from_millis(100));
+    println!("{:?}", block_on(sleepy));
+}
@@ -0,0 +1,14 @@
+<?php
+
+if ($_SERVER['REQUEST_METHOD'] === 'POST') {
+    setcookie('name', $_POST['name']);
+    header('Location: /?page=' . $_GET['page']);
+    http_response_code(303);
+    exit;
+}
+
+header('Content-Type: text/plain');
+header('X-Powered-By: nobody');
+header_remove('X-Powered-By');
+
+printf("Hello %s, this is page %s\n", $_COOKIE['name'] ?? 'stranger', $_GET['page'] ?? 1);
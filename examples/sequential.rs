@@ -0,0 +1,18 @@
+use libphp::exec::Context;
+
+fn main() {
+    // Every context runs its own request on the same engine, which is booted only once.
+    for round in 1..=3 {
+        let mut context = Context::new();
+        context.on_init(move |ctx| ctx.define("ROUND", round));
+
+        let value = context
+            .result_of("defined('PREVIOUS') ? -1 : ROUND * 10", false)
+            .unwrap();
+        println!("Round {} returned {}", round, value.to_int());
+
+        context
+            .result_of("define('PREVIOUS', true)", false)
+            .unwrap();
+    }
+}
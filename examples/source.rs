@@ -0,0 +1,23 @@
+use libphp::exec::Context;
+
+const SOURCE: &str = r#"<?php
+declare(strict_types=1);
+
+function greet(string $name): string {
+    return "Hello, $name!";
+}
+
+echo greet('world'), PHP_EOL;
+echo 'Running ', __FILE__, ' from ', __DIR__, PHP_EOL;
+
+return strlen(greet('Rust'));
+"#;
+
+fn main() {
+    let mut context = Context::new();
+
+    let return_value = context
+        .execute_source(SOURCE, "/virtual/app/greet.php")
+        .unwrap();
+    println!("Return value of script: {:?}", return_value);
+}
@@ -0,0 +1,37 @@
+use libphp::{
+    supervisor::{Supervisor, SupervisorConfig, WorkerError},
+    value::OwnedValue,
+};
+
+// Build the worker first with `cargo build --bin libphp-worker`.
+fn main() -> Result<(), WorkerError> {
+    // Examples are placed in target/<profile>/examples, next to the binaries' directory.
+    let worker_program = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|dir| dir.parent())
+        .unwrap()
+        .join("libphp-worker");
+
+    let supervisor = Supervisor::new(SupervisorConfig {
+        worker_program,
+        workers: 2,
+        max_requests: Some(100),
+        ..Default::default()
+    })?;
+
+    let response = supervisor.call("strtoupper", vec![OwnedValue::from("hello")])?;
+    println!(
+        "strtoupper returned {:?}",
+        response.value.as_ref().map(OwnedValue::as_str)
+    );
+
+    let response = supervisor.eval("print('Hello from a worker!')")?;
+    println!("Output: {}", String::from_utf8_lossy(&response.output));
+
+    // exit() only ends the job, and the worker carries on with the next one.
+    println!("{:?}", supervisor.eval("exit(3)")?.value);
+    println!("{:?}", supervisor.eval("1 + 1")?.value);
+
+    Ok(())
+}
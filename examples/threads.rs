@@ -0,0 +1,26 @@
+use std::{sync::Arc, thread};
+
+use libphp::exec::Engine;
+
+fn main() {
+    let engine = Arc::new(Engine::boot());
+
+    let workers = (1..=4)
+        .map(|worker| {
+            let engine = engine.clone();
+
+            thread::spawn(move || {
+                engine.request(move |ctx| {
+                    ctx.define("WORKER", worker);
+                    ctx.result_of("array_sum(range(1, WORKER * 1000))", false)
+                        .unwrap()
+                        .to_int()
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (worker, handle) in workers.into_iter().enumerate() {
+        println!("Worker {} computed {}", worker + 1, handle.join().unwrap());
+    }
+}
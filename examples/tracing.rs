@@ -0,0 +1,23 @@
+use libphp::{
+    diagnostic::ErrorLevel,
+    exec::{Context, TracingErrorHandler},
+};
+
+fn main() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .init();
+
+    let mut context = Context::builder()
+        .error_handler(ErrorLevel::ALL, TracingErrorHandler)
+        .build();
+
+    // The warning and the error_log() message are emitted as events inside the span of
+    // the script.
+    context
+        .execute_source(
+            "<?php echo $missing; error_log('Hello from PHP'); echo 'Done', PHP_EOL;",
+            "tracing.php",
+        )
+        .unwrap();
+}
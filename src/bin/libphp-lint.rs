@@ -0,0 +1,91 @@
+//! Check PHP files for syntax errors without executing them, like `php -l`.
+//!
+//! Usage: `libphp-lint [-q] [PATH]...`
+//!
+//! Directories are searched recursively for `.php` files. Without any paths, the source is
+//! read from standard input. The exit code is 255 if any file fails to compile.
+
+use std::{
+    env,
+    io::Read,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use libphp::exec::{Context, LintReport};
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+
+    let mut entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            eprintln!("Could not read directory {}: {}", path.display(), err);
+            return;
+        }
+    };
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "php") {
+            collect_files(&entry, files);
+        }
+    }
+}
+
+fn report(name: &str, report: &LintReport, quiet: bool) {
+    for diagnostic in &report.diagnostics {
+        eprintln!("PHP {}", diagnostic);
+    }
+
+    if !report.is_ok() {
+        println!("Errors parsing {}", name);
+    } else if !quiet {
+        println!("No syntax errors detected in {}", name);
+    }
+}
+
+fn main() -> ExitCode {
+    let mut quiet = false;
+    let mut files = Vec::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            path => collect_files(Path::new(path), &mut files),
+        }
+    }
+
+    let mut context = Context::new();
+    let mut failed = false;
+
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("Could not read standard input: {}", err);
+            return ExitCode::from(1);
+        }
+
+        let lint = context.lint_source(&source);
+        report("Standard input code", &lint, quiet);
+        failed = !lint.is_ok();
+    }
+
+    for file in files {
+        let name = file.to_string_lossy();
+        let lint = context.lint_file(&name);
+        report(&name, &lint, quiet);
+        failed |= !lint.is_ok();
+    }
+
+    if failed {
+        ExitCode::from(255)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,1103 @@
+//! A web server for PHP applications, for local development and small deployments.
+//!
+//! Usage: `libphp-serve [-l ADDR] [-t ROOT] [-w WORKERS] [-q] [ROUTER]`
+//!
+//! It is built with the `serve` feature, like `cargo run --features serve --bin libphp-serve`.
+//!
+//! Files in the document root (`-t`, the current directory by default) are served as they
+//! are, and `.php` files are run by a pool of `WORKERS` PHP threads. A directory is served
+//! by its `index.php` or `index.html`. Requests that match no file are passed to the front
+//! controller `ROUTER`, a script relative to the document root like `index.php`, or
+//! answered with `404 Not Found` without one.
+//!
+//! The output of scripts is streamed to the client while they run. Connections are kept
+//! alive between requests, and up to 256 are served at once; more wait to be accepted.
+//! Every request is logged to standard output in the Common Log Format, unless `-q` is
+//! given, and errors are logged to standard error.
+
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, File},
+    future::Future,
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    pin::pin,
+    process::ExitCode,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libphp::{
+    error::PhpError,
+    exec::{PhpPool, PoolError},
+    sapi::http::{HttpExchange, HttpRequest, HttpResponse, HttpSapi, ResponseSink},
+};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    filter::Targets, fmt, layer::SubscriberExt, util::SubscriberInitExt, Layer,
+};
+
+const USAGE: &str = "Usage: libphp-serve [-l ADDR] [-t ROOT] [-w WORKERS] [-q] [ROUTER]";
+
+/// How long an idle connection is kept open for the next request.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the rest of a rejected request is waited for before closing its connection.
+const LINGER_TIMEOUT: Duration = Duration::from_secs(1);
+/// The longest request line or header block that is accepted.
+const MAX_HEAD: usize = 64 * 1024;
+const MAX_BODY: usize = 64 * 1024 * 1024;
+/// The most connections that are served at once.
+const MAX_CONNECTIONS: usize = 256;
+/// The target of the access log events.
+const ACCESS_LOG: &str = "libphp_serve::access";
+
+struct Options {
+    listen: String,
+    root: PathBuf,
+    workers: usize,
+    quiet: bool,
+    router: Option<String>,
+}
+
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        listen: "127.0.0.1:8000".to_string(),
+        root: PathBuf::from("."),
+        workers: thread::available_parallelism().map_or(4, NonZeroUsize::get),
+        quiet: false,
+        router: None,
+    };
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" | "--listen" => options.listen = value(&mut args, &arg)?,
+            "-t" | "--root" => options.root = value(&mut args, &arg)?.into(),
+            "-w" | "--workers" => {
+                options.workers = value(&mut args, &arg)?
+                    .parse()
+                    .ok()
+                    .filter(|&workers| workers > 0)
+                    .ok_or_else(|| format!("{} must be a positive number", arg))?;
+            }
+            "-q" | "--quiet" => options.quiet = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if options.router.is_none() => options.router = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+// The pool's jobs are futures; connection threads simply wait for them.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// A point in time in UTC, for the `Date` header and the access log.
+struct UtcTime {
+    year: i64,
+    month: usize,
+    day: i64,
+    weekday: usize,
+    seconds: i64,
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+impl UtcTime {
+    fn new(time: SystemTime) -> Self {
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64);
+        let days = timestamp.div_euclid(86400);
+
+        // Convert days since the epoch to a date in the proleptic Gregorian calendar.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+
+        Self {
+            year: year_of_era + era * 400 + i64::from(month <= 2),
+            month: month as usize,
+            day: day_of_year - (153 * shifted_month + 2) / 5 + 1,
+            // 1970-01-01 was a Thursday.
+            weekday: (days + 4).rem_euclid(7) as usize,
+            seconds: timestamp.rem_euclid(86400),
+        }
+    }
+
+    fn now() -> Self {
+        Self::new(SystemTime::now())
+    }
+
+    fn clock(&self) -> String {
+        format!(
+            "{:02}:{:02}:{:02}",
+            self.seconds / 3600,
+            self.seconds / 60 % 60,
+            self.seconds % 60
+        )
+    }
+
+    /// Format as in HTTP headers, like `Sun, 06 Nov 1994 08:49:37 GMT`.
+    fn http_date(&self) -> String {
+        format!(
+            "{}, {:02} {} {} {} GMT",
+            WEEKDAYS[self.weekday],
+            self.day,
+            MONTHS[self.month - 1],
+            self.year,
+            self.clock()
+        )
+    }
+
+    /// Format as in the Common Log Format, like `06/Nov/1994:08:49:37 +0000`.
+    fn log_date(&self) -> String {
+        format!(
+            "{:02}/{}/{}:{} +0000",
+            self.day,
+            MONTHS[self.month - 1],
+            self.year,
+            self.clock()
+        )
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Content",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("xml") => "application/xml",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("zip") => "application/zip",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("mp3") => "audio/mpeg",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Why a connection stopped before a whole request was read.
+enum ReadError {
+    /// The client closed the connection, went idle, or the connection failed.
+    Closed,
+    /// The request is invalid, and is answered with this status before closing.
+    Status(u16),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(_: io::Error) -> Self {
+        Self::Closed
+    }
+}
+
+/// Read a line without its line ending, counting it against `budget`.
+fn read_line(reader: &mut impl BufRead, budget: &mut usize) -> Result<String, ReadError> {
+    let mut line = Vec::new();
+    let read = reader
+        .by_ref()
+        .take(*budget as u64)
+        .read_until(b'\n', &mut line)?;
+
+    if !line.ends_with(b"\n") {
+        return Err(if read > 0 && read == *budget {
+            ReadError::Status(431)
+        } else {
+            ReadError::Closed
+        });
+    }
+    *budget -= read;
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+fn read_chunked(reader: &mut impl BufRead, body: &mut Vec<u8>) -> Result<(), ReadError> {
+    let mut budget = MAX_HEAD;
+
+    loop {
+        let line = read_line(reader, &mut budget)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| ReadError::Status(400))?;
+
+        if size == 0 {
+            // Skip the trailers.
+            while !read_line(reader, &mut budget)?.is_empty() {}
+            return Ok(());
+        }
+        let start = body.len();
+        let end = match start.checked_add(size) {
+            Some(end) if end <= MAX_BODY => end,
+            _ => return Err(ReadError::Status(413)),
+        };
+
+        body.resize(end, 0);
+        reader.read_exact(&mut body[start..])?;
+
+        if !read_line(reader, &mut budget)?.is_empty() {
+            return Err(ReadError::Status(400));
+        }
+    }
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> Result<HttpRequest, ReadError> {
+    let mut budget = MAX_HEAD;
+
+    let mut line = read_line(reader, &mut budget)?;
+    // Clients may send empty lines between requests.
+    while line.is_empty() {
+        line = read_line(reader, &mut budget)?;
+    }
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(uri), Some(protocol), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ReadError::Status(400));
+    };
+    if !uri.starts_with('/') {
+        return Err(ReadError::Status(400));
+    }
+    if protocol != "HTTP/1.1" && protocol != "HTTP/1.0" {
+        return Err(ReadError::Status(505));
+    }
+
+    let mut request = HttpRequest::new(method, uri);
+    request.protocol = protocol.to_string();
+
+    loop {
+        let line = read_line(reader, &mut budget)?;
+        if line.is_empty() {
+            break;
+        }
+
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(ReadError::Status(400));
+        };
+        request
+            .headers
+            .push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let chunked = request
+        .header_value("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.to_ascii_lowercase().contains("chunked"));
+    let lengths = request.header_values("Content-Length").collect::<Vec<_>>();
+    let length = match lengths[..] {
+        [] => 0,
+        [length] if !chunked => {
+            if length.is_empty() || !length.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(ReadError::Status(400));
+            }
+            length.parse().unwrap_or(usize::MAX)
+        }
+        // Proxies may disagree on which of several lengths or framings applies, which
+        // allows smuggling requests past them.
+        _ => return Err(ReadError::Status(400)),
+    };
+    if length > MAX_BODY {
+        return Err(ReadError::Status(413));
+    }
+
+    if (chunked || length > 0)
+        && request
+            .header_value("Expect")
+            .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+    {
+        let mut stream = reader.get_ref();
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+
+    if chunked {
+        read_chunked(reader, &mut request.body)?;
+    } else {
+        request.body.resize(length, 0);
+        reader.read_exact(&mut request.body)?;
+    }
+
+    Ok(request)
+}
+
+/// Close a connection after rejecting its request. Closing it with part of the request
+/// unread would reset it, which can discard the response before the client reads it.
+fn linger(reader: &mut BufReader<TcpStream>) {
+    let _ = reader.get_ref().shutdown(Shutdown::Write);
+    let _ = reader.get_ref().set_read_timeout(Some(LINGER_TIMEOUT));
+    let _ = io::copy(&mut reader.take(MAX_HEAD as u64), &mut io::sink());
+}
+
+fn keep_alive(request: &HttpRequest) -> bool {
+    let has_token = |token: &str| {
+        request
+            .header_values("Connection")
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+
+    if request.protocol == "HTTP/1.0" {
+        has_token("keep-alive")
+    } else {
+        !has_token("close")
+    }
+}
+
+enum Body {
+    Bytes(Vec<u8>),
+    File(File, u64),
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+impl Response {
+    fn error(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![(
+                "Content-Type".to_string(),
+                "text/plain; charset=utf-8".to_string(),
+            )],
+            body: Body::Bytes(format!("{} {}\n", status, reason(status)).into_bytes()),
+        }
+    }
+}
+
+fn has_body(status: u16) -> bool {
+    status >= 200 && status != 204 && status != 304
+}
+
+/// How the client finds the end of a response body. Without one, the body ends when the
+/// connection is closed.
+enum Framing {
+    Length(u64),
+    Chunked,
+}
+
+fn write_head(
+    out: &mut impl Write,
+    status: u16,
+    headers: &[(String, String)],
+    keep_alive: bool,
+    framing: Option<Framing>,
+) -> io::Result<()> {
+    write!(out, "HTTP/1.1 {} {}\r\n", status, reason(status))?;
+    for (name, value) in headers {
+        // The server frames the body and manages the connection itself.
+        if ["Content-Length", "Transfer-Encoding", "Connection"]
+            .iter()
+            .any(|header| name.eq_ignore_ascii_case(header))
+        {
+            continue;
+        }
+        write!(out, "{}: {}\r\n", name, value)?;
+    }
+    write!(out, "Date: {}\r\n", UtcTime::now().http_date())?;
+    write!(
+        out,
+        "Connection: {}\r\n",
+        if keep_alive { "keep-alive" } else { "close" }
+    )?;
+    match framing {
+        Some(Framing::Length(length)) => write!(out, "Content-Length: {}\r\n", length)?,
+        Some(Framing::Chunked) => out.write_all(b"Transfer-Encoding: chunked\r\n")?,
+        None => {}
+    }
+    out.write_all(b"\r\n")
+}
+
+/// Write a response, returning the length of the body that was sent.
+fn write_response(
+    stream: &TcpStream,
+    response: Response,
+    head_only: bool,
+    keep_alive: bool,
+) -> io::Result<u64> {
+    let mut out = BufWriter::new(stream);
+    let status = response.status;
+    let length = match &response.body {
+        Body::Bytes(bytes) => bytes.len() as u64,
+        Body::File(_, length) => *length,
+    };
+
+    write_head(
+        &mut out,
+        status,
+        &response.headers,
+        keep_alive,
+        has_body(status).then_some(Framing::Length(length)),
+    )?;
+
+    if !has_body(status) || head_only {
+        out.flush()?;
+        return Ok(0);
+    }
+
+    let sent = match response.body {
+        Body::Bytes(bytes) => {
+            out.write_all(&bytes)?;
+            bytes.len() as u64
+        }
+        Body::File(file, length) => io::copy(&mut file.take(length), &mut out)?,
+    };
+    out.flush()?;
+
+    if sent < length {
+        // The file was truncated while it was sent, so the framing is broken.
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(sent)
+}
+
+/// The response of a script, which is written to the connection while PHP produces it.
+struct ScriptOutput {
+    out: BufWriter<TcpStream>,
+    head_only: bool,
+    /// Whether the client understands a chunked body, or the body ends with the connection.
+    chunked: bool,
+    keep_alive: bool,
+    /// The status, once the head was sent.
+    status: Option<u16>,
+    sent: u64,
+    error: Option<io::Error>,
+    finished: bool,
+}
+
+impl ScriptOutput {
+    fn has_body(&self) -> bool {
+        self.status.is_some_and(has_body) && !self.head_only
+    }
+
+    fn write(&mut self, write: impl FnOnce(&mut BufWriter<TcpStream>) -> io::Result<()>) {
+        if self.error.is_none() {
+            if let Err(err) = write(&mut self.out).and_then(|_| self.out.flush()) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    /// End the body, returning the status and the length of the body that was sent, or
+    /// `None` if the script never sent the head.
+    fn finish(&mut self) -> Option<(u16, io::Result<u64>)> {
+        self.finished = true;
+        let status = self.status?;
+
+        if self.has_body() && self.chunked {
+            self.write(|out| out.write_all(b"0\r\n\r\n"));
+        }
+        Some((status, self.error.take().map_or(Ok(self.sent), Err)))
+    }
+}
+
+/// Passes the response from [`HttpSapi`] to the connection.
+struct ConnectionSink(Arc<Mutex<ScriptOutput>>);
+
+impl ResponseSink for ConnectionSink {
+    fn send_head(&mut self, response: &HttpResponse) {
+        let mut output = lock(&self.0);
+        if output.finished || output.status.is_some() {
+            return;
+        }
+
+        output.status = Some(response.status);
+        let framing = if !output.has_body() {
+            None
+        } else if output.chunked {
+            Some(Framing::Chunked)
+        } else {
+            output.keep_alive = false;
+            None
+        };
+        let keep_alive = output.keep_alive;
+
+        output
+            .write(|out| write_head(out, response.status, &response.headers, keep_alive, framing));
+    }
+
+    fn write_body(&mut self, bytes: &[u8]) {
+        let mut output = lock(&self.0);
+        if output.finished || !output.has_body() || bytes.is_empty() {
+            return;
+        }
+
+        let chunked = output.chunked;
+        output.write(|out| {
+            if chunked {
+                write!(out, "{:x}\r\n", bytes.len())?;
+                out.write_all(bytes)?;
+                out.write_all(b"\r\n")
+            } else {
+                out.write_all(bytes)
+            }
+        });
+        output.sent += bytes.len() as u64;
+    }
+}
+
+/// What serves a request.
+enum Route {
+    Script {
+        script_name: String,
+        path_info: Option<String>,
+    },
+    File(PathBuf),
+}
+
+fn is_php(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("php"))
+}
+
+/// Counts the connections being served, so no more than [`MAX_CONNECTIONS`] are.
+#[derive(Default)]
+struct Connections {
+    count: Mutex<usize>,
+    closed: Condvar,
+}
+
+/// One of the [`Connections`], which is released when dropped.
+struct ConnectionSlot(Arc<Connections>);
+
+impl Connections {
+    /// Wait until fewer than [`MAX_CONNECTIONS`] connections are served, and take a slot.
+    fn acquire(self: &Arc<Self>) -> ConnectionSlot {
+        let mut count = lock(&self.count);
+
+        while *count >= MAX_CONNECTIONS {
+            count = self
+                .closed
+                .wait(count)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        *count += 1;
+
+        ConnectionSlot(self.clone())
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        *lock(&self.0.count) -= 1;
+        self.0.closed.notify_one();
+    }
+}
+
+struct Server {
+    root: PathBuf,
+    document_root: String,
+    router: Option<String>,
+    pool: PhpPool<HttpSapi>,
+}
+
+impl Server {
+    fn contains(&self, path: &Path) -> bool {
+        path.canonicalize()
+            .is_ok_and(|path| path.starts_with(&self.root))
+    }
+
+    /// Find the file or script for a request path, without the front controller.
+    fn find(&self, segments: &[&str]) -> Option<Route> {
+        let mut path = self.root.clone();
+
+        for (i, segment) in segments.iter().enumerate() {
+            path.push(segment);
+
+            let metadata = fs::metadata(&path).ok()?;
+            if metadata.is_dir() {
+                continue;
+            }
+
+            // Symlinks may point outside of the document root.
+            if !self.contains(&path) {
+                return None;
+            }
+
+            let rest = &segments[i + 1..];
+            return if is_php(&path) {
+                Some(Route::Script {
+                    script_name: format!("/{}", segments[..=i].join("/")),
+                    path_info: (!rest.is_empty()).then(|| format!("/{}", rest.join("/"))),
+                })
+            } else if rest.is_empty() {
+                Some(Route::File(path))
+            } else {
+                None
+            };
+        }
+
+        for index in ["index.php", "index.html"] {
+            let file = path.join(index);
+
+            if file.is_file() && self.contains(&file) {
+                return Some(if is_php(&file) {
+                    Route::Script {
+                        script_name: segments
+                            .iter()
+                            .chain([&index])
+                            .map(|segment| format!("/{}", segment))
+                            .collect(),
+                        path_info: None,
+                    }
+                } else {
+                    Route::File(file)
+                });
+            }
+        }
+
+        None
+    }
+
+    fn route(&self, request: &HttpRequest) -> Result<Route, u16> {
+        let path = percent_decode(request.path()).ok_or(400u16)?;
+        let mut segments = Vec::new();
+
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => return Err(400),
+                _ if segment.contains('\\') => return Err(400),
+                _ => segments.push(segment),
+            }
+        }
+
+        match (self.find(&segments), &self.router) {
+            (Some(route), _) => Ok(route),
+            (None, Some(router)) => Ok(Route::Script {
+                script_name: format!("/{}", router),
+                path_info: None,
+            }),
+            (None, None) => Err(404),
+        }
+    }
+
+    /// Run a script, streaming its response to `stream`, and return the status and the
+    /// length of the body that was sent.
+    fn run_script(
+        &self,
+        request: HttpRequest,
+        script_name: String,
+        path_info: Option<String>,
+        stream: &TcpStream,
+        keep_alive: &mut bool,
+    ) -> (u16, io::Result<u64>) {
+        let head_only = request.method == "HEAD";
+        let output = match stream.try_clone() {
+            Ok(out) => Arc::new(Mutex::new(ScriptOutput {
+                out: BufWriter::new(out),
+                head_only,
+                chunked: request.protocol == "HTTP/1.1",
+                keep_alive: *keep_alive,
+                status: None,
+                sent: 0,
+                error: None,
+                finished: false,
+            })),
+            Err(err) => return (500, Err(err)),
+        };
+
+        let mut request = request.script(&self.document_root, script_name);
+        request.path_info = path_info;
+
+        let exchange = HttpExchange::with_sink(request, ConnectionSink(output.clone()));
+        let job = self.pool.run_with(Box::new(exchange), |ctx| {
+            let script = ctx.sapi_context().request.script_filename.clone();
+            let result = ctx.execute_file(&script, false).map(drop);
+
+            // Shutting the request down sends the headers and flushes the output buffers.
+            ctx.close();
+            result
+        });
+
+        let result = block_on(job);
+        match &result {
+            Ok(Ok(()) | Err(PhpError::Exit(_))) => {}
+            Ok(Err(err)) => tracing::warn!("PHP request failed: {}", err),
+            Err(err) => tracing::error!("PHP request failed: {}", err),
+        }
+
+        let mut output = lock(&output);
+        *keep_alive = output.keep_alive;
+
+        output.finish().unwrap_or_else(|| {
+            // The job ended before PHP sent anything, so the connection is still clean.
+            let status = match result {
+                Err(PoolError::Closed) => 503,
+                Err(PoolError::Timeout) => 504,
+                _ => 500,
+            };
+            let response = Response::error(status);
+            (
+                status,
+                write_response(stream, response, head_only, *keep_alive),
+            )
+        })
+    }
+
+    fn serve_file(&self, request: &HttpRequest, path: &Path) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            let mut response = Response::error(405);
+            response
+                .headers
+                .push(("Allow".to_string(), "GET, HEAD".to_string()));
+            return response;
+        }
+
+        let (file, metadata) = match File::open(path).and_then(|file| {
+            let metadata = file.metadata()?;
+            Ok((file, metadata))
+        }) {
+            Ok(file) => file,
+            Err(err) => {
+                tracing::warn!("Could not read {}: {}", path.display(), err);
+                return Response::error(if err.kind() == io::ErrorKind::PermissionDenied {
+                    403
+                } else {
+                    404
+                });
+            }
+        };
+
+        let mut headers = vec![("Content-Type".to_string(), content_type(path).to_string())];
+        if let Ok(modified) = metadata.modified() {
+            headers.push((
+                "Last-Modified".to_string(),
+                UtcTime::new(modified).http_date(),
+            ));
+        }
+
+        Response {
+            status: 200,
+            headers,
+            body: Body::File(file, metadata.len()),
+        }
+    }
+
+    /// Answer a request on `stream`, and return the status and the length of the body that
+    /// was sent.
+    fn respond(
+        &self,
+        request: HttpRequest,
+        stream: &TcpStream,
+        keep_alive: &mut bool,
+    ) -> (u16, io::Result<u64>) {
+        let head_only = request.method == "HEAD";
+        let response = match self.route(&request) {
+            Ok(Route::Script {
+                script_name,
+                path_info,
+            }) => return self.run_script(request, script_name, path_info, stream, keep_alive),
+            Ok(Route::File(path)) => self.serve_file(&request, &path),
+            Err(status) => Response::error(status),
+        };
+        let status = response.status;
+
+        (
+            status,
+            write_response(stream, response, head_only, *keep_alive),
+        )
+    }
+
+    fn log(&self, peer: Option<SocketAddr>, request_line: &str, status: u16, sent: u64) {
+        tracing::info!(
+            target: ACCESS_LOG,
+            "{} - - [{}] \"{}\" {} {}",
+            peer.map_or("-".to_string(), |peer| peer.ip().to_string()),
+            UtcTime::now().log_date(),
+            request_line.escape_default(),
+            status,
+            if sent > 0 {
+                sent.to_string()
+            } else {
+                "-".to_string()
+            }
+        );
+    }
+
+    fn serve_connection(&self, stream: TcpStream) {
+        let peer = stream.peer_addr().ok();
+        let local = stream.local_addr().ok();
+
+        // Responses are flushed once written, so there is nothing to gain from Nagle's algorithm.
+        let _ = stream.set_nodelay(true);
+        let _ = stream.set_read_timeout(Some(KEEP_ALIVE_TIMEOUT));
+        let mut reader = BufReader::new(stream);
+
+        loop {
+            let mut request = match read_request(&mut reader) {
+                Ok(request) => request,
+                Err(ReadError::Closed) => return,
+                Err(ReadError::Status(status)) => {
+                    let sent =
+                        write_response(reader.get_ref(), Response::error(status), false, false);
+                    self.log(peer, "-", status, sent.unwrap_or(0));
+                    linger(&mut reader);
+                    return;
+                }
+            };
+
+            request.remote_addr = peer;
+            request.server_addr = local;
+
+            let request_line = format!("{} {} {}", request.method, request.uri, request.protocol);
+            let mut keep_alive = keep_alive(&request);
+
+            let (status, sent) = self.respond(request, reader.get_ref(), &mut keep_alive);
+
+            match sent {
+                Ok(sent) => self.log(peer, &request_line, status, sent),
+                Err(_) => {
+                    self.log(peer, &request_line, status, 0);
+                    return;
+                }
+            }
+
+            if !keep_alive {
+                return;
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    if env::args().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("libphp-serve: {}\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Requests are logged to standard output, everything else to standard error.
+    let access_log = (!options.quiet).then(|| {
+        fmt::layer()
+            .with_writer(io::stdout)
+            .without_time()
+            .with_level(false)
+            .with_target(false)
+            .with_ansi(false)
+            .with_filter(Targets::new().with_target(ACCESS_LOG, LevelFilter::INFO))
+    });
+    let errors = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_filter(
+            Targets::new()
+                .with_target(ACCESS_LOG, LevelFilter::OFF)
+                .with_default(LevelFilter::INFO),
+        );
+    tracing_subscriber::registry()
+        .with(access_log)
+        .with(errors)
+        .init();
+
+    let root = match options.root.canonicalize() {
+        Ok(root) if root.is_dir() => root,
+        _ => {
+            eprintln!(
+                "libphp-serve: Document root {} is not a directory",
+                options.root.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let router = options
+        .router
+        .map(|router| router.trim_start_matches('/').to_string());
+    if let Some(router) = &router {
+        if !root.join(router).is_file() {
+            eprintln!(
+                "libphp-serve: Router {} does not exist in {}",
+                router,
+                root.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let listener = match TcpListener::bind(&options.listen) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!(
+                "libphp-serve: Could not listen on {}: {}",
+                options.listen, err
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let server = Arc::new(Server {
+        document_root: root.to_string_lossy().into_owned(),
+        root,
+        router,
+        pool: PhpPool::new_with_sapi(options.workers, options.workers),
+    });
+    let connections = Arc::new(Connections::default());
+
+    tracing::info!(
+        "Serving {} on http://{} with {} workers",
+        server.document_root,
+        listener
+            .local_addr()
+            .map_or(options.listen, |addr| addr.to_string()),
+        options.workers
+    );
+
+    loop {
+        let slot = connections.acquire();
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                tracing::warn!("Could not accept connection: {}", err);
+                continue;
+            }
+        };
+        let server = server.clone();
+
+        if let Err(err) = thread::Builder::new()
+            .name("libphp-serve-connection".to_string())
+            .spawn(move || {
+                server.serve_connection(stream);
+                drop(slot);
+            })
+        {
+            tracing::error!("Could not spawn connection thread: {}", err);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! A worker process for `libphp::supervisor::Supervisor`, which runs PHP jobs sent over a
+//! socket it inherits.
+//!
+//! Usage: `libphp-worker FD`
+
+use std::process::ExitCode;
+
+#[cfg(unix)]
+fn main() -> ExitCode {
+    use std::os::unix::{io::FromRawFd, net::UnixStream};
+
+    let Some(fd) = std::env::args().nth(1).and_then(|fd| fd.parse().ok()) else {
+        eprintln!("Usage: libphp-worker FD");
+        return ExitCode::FAILURE;
+    };
+
+    // The supervisor keeps this descriptor open for the worker only.
+    let inherited = unsafe { UnixStream::from_raw_fd(fd) };
+    // Unlike the inherited descriptor, a copy isn't passed on to processes PHP starts.
+    let stream = inherited.try_clone();
+    drop(inherited);
+
+    match stream.and_then(libphp::supervisor::run_worker) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("libphp-worker: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() -> ExitCode {
+    eprintln!("libphp-worker is only supported on Unix");
+    ExitCode::FAILURE
+}
@@ -0,0 +1,184 @@
+use std::{
+    ffi::{c_char, c_int},
+    fmt::Display,
+    ops::BitOr,
+};
+
+/// A PHP error level, or a mask of several (the `E_*` constants).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorLevel(pub i32);
+
+impl ErrorLevel {
+    pub const ERROR: Self = Self(1 << 0);
+    pub const WARNING: Self = Self(1 << 1);
+    pub const PARSE: Self = Self(1 << 2);
+    pub const NOTICE: Self = Self(1 << 3);
+    pub const CORE_ERROR: Self = Self(1 << 4);
+    pub const CORE_WARNING: Self = Self(1 << 5);
+    pub const COMPILE_ERROR: Self = Self(1 << 6);
+    pub const COMPILE_WARNING: Self = Self(1 << 7);
+    pub const USER_ERROR: Self = Self(1 << 8);
+    pub const USER_WARNING: Self = Self(1 << 9);
+    pub const USER_NOTICE: Self = Self(1 << 10);
+    pub const STRICT: Self = Self(1 << 11);
+    pub const RECOVERABLE_ERROR: Self = Self(1 << 12);
+    pub const DEPRECATED: Self = Self(1 << 13);
+    pub const USER_DEPRECATED: Self = Self(1 << 14);
+    pub const ALL: Self = Self((1 << 15) - 1);
+
+    /// Errors that abort the script.
+    pub const FATAL: Self = Self(
+        Self::ERROR.0
+            | Self::CORE_ERROR.0
+            | Self::COMPILE_ERROR.0
+            | Self::USER_ERROR.0
+            | Self::RECOVERABLE_ERROR.0
+            | Self::PARSE.0,
+    );
+
+    /// Check if all levels in `other` are part of this mask.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Check if this mask and `other` have any level in common.
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Check if the level aborts the script.
+    pub fn is_fatal(self) -> bool {
+        self.intersects(Self::FATAL)
+    }
+
+    /// Get how serious an error of this level is.
+    pub fn severity(self) -> Severity {
+        if self.is_fatal() {
+            Severity::Error
+        } else if self.intersects(Self::DEPRECATED | Self::USER_DEPRECATED) {
+            Severity::Deprecated
+        } else if self.intersects(Self::NOTICE | Self::USER_NOTICE | Self::STRICT) {
+            Severity::Notice
+        } else {
+            Severity::Warning
+        }
+    }
+
+    /// Get the label PHP uses when displaying an error of this level.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ERROR | Self::CORE_ERROR | Self::COMPILE_ERROR | Self::USER_ERROR => {
+                "Fatal error"
+            }
+            Self::RECOVERABLE_ERROR => "Recoverable fatal error",
+            Self::PARSE => "Parse error",
+            Self::WARNING | Self::CORE_WARNING | Self::COMPILE_WARNING | Self::USER_WARNING => {
+                "Warning"
+            }
+            Self::NOTICE | Self::USER_NOTICE => "Notice",
+            Self::STRICT => "Strict Standards",
+            Self::DEPRECATED | Self::USER_DEPRECATED => "Deprecated",
+            _ => "Unknown error",
+        }
+    }
+}
+
+impl BitOr for ErrorLevel {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// How serious a diagnostic is, independent of where it was raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Deprecated,
+    Notice,
+    Warning,
+    Error,
+}
+
+/// An error, warning or notice raised by PHP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhpDiagnostic {
+    pub level: ErrorLevel,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: u32,
+}
+
+impl PhpDiagnostic {
+    /// Build a diagnostic from the arguments PHP passes to its error callback.
+    ///
+    /// # Safety
+    ///
+    /// `file` and `message` must be valid for reads of `file_len` and `message_len` bytes.
+    /// `file` may be null.
+    pub(crate) unsafe fn from_raw(
+        level: c_int,
+        file: *const c_char,
+        file_len: usize,
+        line: u32,
+        message: *const c_char,
+        message_len: usize,
+    ) -> Self {
+        let file = (!file.is_null()).then(|| {
+            let file = std::slice::from_raw_parts(file as *const u8, file_len);
+            String::from_utf8_lossy(file).into_owned()
+        });
+        let message = std::slice::from_raw_parts(message as *const u8, message_len);
+
+        Self {
+            level: ErrorLevel(level),
+            message: String::from_utf8_lossy(message).into_owned(),
+            file,
+            line,
+        }
+    }
+
+    /// Get how serious the diagnostic is.
+    pub fn severity(&self) -> Severity {
+        self.level.severity()
+    }
+}
+
+impl Display for PhpDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.level.label(), self.message)?;
+
+        match &self.file {
+            Some(file) => write!(f, " in {} on line {}", file, self.line),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What to do with a diagnostic once an [`ErrorHandler`] has seen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Let PHP display and log the diagnostic as usual.
+    Pass,
+    /// Don't display or log the diagnostic. PHP still records it for `error_get_last()`,
+    /// and fatal errors still stop the code.
+    Handled,
+    /// Stop the code as if the diagnostic was a fatal error, failing with
+    /// [`PhpError::Escalated`](crate::error::PhpError::Escalated).
+    Escalate,
+}
+
+/// Receives the errors, warnings and notices PHP raises, see
+/// [`Context::set_error_handler`](crate::exec::Context::set_error_handler).
+///
+/// Diagnostics handled by an error handler set from PHP with `set_error_handler()` do not
+/// reach it.
+pub trait ErrorHandler {
+    fn handle(&mut self, diagnostic: &PhpDiagnostic) -> ErrorAction;
+}
+
+impl<F: FnMut(&PhpDiagnostic) -> ErrorAction> ErrorHandler for F {
+    fn handle(&mut self, diagnostic: &PhpDiagnostic) -> ErrorAction {
+        self(diagnostic)
+    }
+}
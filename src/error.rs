@@ -0,0 +1,129 @@
+use std::{
+    ffi::{c_char, c_int},
+    fmt::Display,
+    ptr::null,
+};
+
+use crate::{
+    diagnostic::PhpDiagnostic,
+    sys::{
+        libphp_clear_last_error, libphp_exit_watch, libphp_exited,
+        libphp_fatal_reason_LIBPHP_FATAL_INTERRUPTED,
+        libphp_fatal_reason_LIBPHP_FATAL_OUT_OF_MEMORY, libphp_fatal_reason_LIBPHP_FATAL_TIMEOUT,
+        libphp_last_error, libphp_last_fatal_reason,
+    },
+};
+
+/// Why running PHP code failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhpError {
+    /// The code raised a fatal error, or threw an exception that was not caught.
+    ///
+    /// PHP abandons the code without cleaning up after it, so the request cannot run
+    /// anything else. Like every error but [`PhpError::Exit`], this ends the request once
+    /// the call into the [`Context`](crate::exec::Context) that ran the code returns, and
+    /// the next call starts a new one. Globals, functions and the output sink of the
+    /// request do not carry over.
+    Fatal(PhpDiagnostic),
+    /// The code ran longer than its time limit, see
+    /// [`Context::with_limits`](crate::exec::Context::with_limits).
+    Timeout,
+    /// The code exhausted its memory limit, see
+    /// [`Context::with_limits`](crate::exec::Context::with_limits).
+    OutOfMemory,
+    /// The code was stopped through an [`InterruptHandle`](crate::exec::InterruptHandle).
+    Interrupted,
+    /// The code called `exit()` or `die()` with this status, which is 0 if none was given.
+    ///
+    /// Only the code is stopped, not the process, and the request can still be used.
+    Exit(i32),
+    /// An [`ErrorHandler`](crate::diagnostic::ErrorHandler) escalated this error, warning
+    /// or notice, which stopped the code.
+    Escalated(PhpDiagnostic),
+    /// The code did not run, because the request it belongs to has been ended by an
+    /// earlier error.
+    Ended,
+}
+
+/// Detects the fatal errors and `exit()` calls of the code run while it is watching.
+pub(crate) struct ErrorWatch {
+    previous_status: c_int,
+}
+
+impl ErrorWatch {
+    /// Get the error or exit status the code ended with, if any.
+    pub(crate) fn finish(self) -> Result<(), PhpError> {
+        let mut status = 0;
+        let exited = unsafe { libphp_exited(self.previous_status, &mut status) };
+
+        match PhpError::last() {
+            Some(err) => Err(err),
+            None if exited => Err(PhpError::Exit(status)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PhpError {
+    /// Start watching for errors, forgetting the ones raised so far.
+    pub(crate) fn watch() -> ErrorWatch {
+        unsafe { libphp_clear_last_error() };
+
+        ErrorWatch {
+            previous_status: unsafe { libphp_exit_watch() },
+        }
+    }
+
+    /// Get the error that aborted the code that ran last, if it failed.
+    fn last() -> Option<Self> {
+        let mut level: c_int = 0;
+        let mut file: *const c_char = null();
+        let mut file_len = 0;
+        let mut line = 0;
+        let mut message: *const c_char = null();
+        let mut message_len = 0;
+
+        let diagnostic = unsafe {
+            if !libphp_last_error(
+                &mut level,
+                &mut file,
+                &mut file_len,
+                &mut line,
+                &mut message,
+                &mut message_len,
+            ) {
+                return None;
+            }
+
+            PhpDiagnostic::from_raw(level, file, file_len, line, message, message_len)
+        };
+
+        if !diagnostic.level.is_fatal() {
+            return None;
+        }
+
+        // The message can't tell these apart, as user code may raise errors with any message.
+        Some(match unsafe { libphp_last_fatal_reason() } {
+            libphp_fatal_reason_LIBPHP_FATAL_TIMEOUT => Self::Timeout,
+            libphp_fatal_reason_LIBPHP_FATAL_OUT_OF_MEMORY => Self::OutOfMemory,
+            libphp_fatal_reason_LIBPHP_FATAL_INTERRUPTED => Self::Interrupted,
+            _ => Self::Fatal(diagnostic),
+        })
+    }
+}
+
+impl Display for PhpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fatal(diagnostic) => write!(f, "{}", diagnostic),
+            Self::Timeout => write!(f, "PHP code exceeded its time limit"),
+            Self::OutOfMemory => write!(f, "PHP code exhausted its memory limit"),
+            Self::Interrupted => write!(f, "PHP code was interrupted"),
+            Self::Exit(status) => write!(f, "PHP code called exit({})", status),
+            Self::Escalated(diagnostic) => write!(f, "{}", diagnostic),
+            Self::Ended => write!(f, "PHP request has already ended"),
+        }
+    }
+}
+
+impl std::error::Error for PhpError {}
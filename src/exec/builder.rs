@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use crate::{
+    diagnostic::{ErrorHandler, ErrorLevel},
+    sapi::{embedded::EmbeddedSapi, raw::RawPhpSapi},
+};
+
+use super::Context;
+
+/// Configures a [`Context`] before it starts, including PHP's INI settings.
+///
+/// INI files and defaults are read when PHP boots, which happens once per process, so
+/// [`ini_file`](Self::ini_file), [`ini_defaults`](Self::ini_defaults) and
+/// [`php_ini_ignore`](Self::php_ini_ignore) only take effect for the first context that
+/// starts. Settings passed to [`ini`](Self::ini) override everything else; they are passed
+/// to PHP when it boots too, but a context that starts after that can only apply them at
+/// the start of each of its requests.
+pub struct ContextBuilder<'a, Sapi: RawPhpSapi = EmbeddedSapi> {
+    context: Context<'a, Sapi>,
+}
+
+impl<'a> Context<'a, EmbeddedSapi> {
+    /// Configure a new PHP execution context.
+    pub fn builder() -> ContextBuilder<'a, EmbeddedSapi> {
+        ContextBuilder {
+            context: Context::new(),
+        }
+    }
+}
+
+impl<'a, Sapi: RawPhpSapi> ContextBuilder<'a, Sapi> {
+    /// Configure a new PHP execution context with a custom SAPI.
+    pub fn with_sapi(content: Box<Sapi::Context>) -> Self {
+        Self {
+            context: Context::new_with_sapi(content),
+        }
+    }
+
+    /// Set an INI setting for every request of the context, like `-d` does for `php-cli`.
+    ///
+    /// If this context boots PHP, the setting is applied at startup, so settings such as
+    /// `output_buffering` or `auto_prepend_file` that PHP reads before a request runs take
+    /// effect. Otherwise it is set like [`Context::ini_set`] when each request starts, which
+    /// is too late for those, and a warning is logged.
+    pub fn ini(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let setting = (name.into(), value.into());
+
+        self.context.boot.ini_entries.push(setting.clone());
+        self.context.ini.push(setting);
+        self
+    }
+
+    /// Read this INI file, or the `php.ini` in this directory, instead of searching for
+    /// one, like `-c` does for `php-cli`.
+    pub fn ini_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.context.boot.ini_file = Some(path.into());
+        self
+    }
+
+    /// Set INI settings that INI files can override.
+    pub fn ini_defaults<N: Into<String>, V: Into<String>>(
+        mut self,
+        settings: impl IntoIterator<Item = (N, V)>,
+    ) -> Self {
+        self.context.boot.ini_defaults.extend(
+            settings
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into())),
+        );
+        self
+    }
+
+    /// Don't read any INI file, like `-n` does for `php-cli`.
+    pub fn php_ini_ignore(mut self) -> Self {
+        self.context.boot.php_ini_ignore = true;
+        self
+    }
+
+    /// Set the command line arguments of the script, starting with its name.
+    pub fn argv<S: Into<String>>(mut self, argv: impl IntoIterator<Item = S>) -> Self {
+        self.context
+            .argv(argv.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Pass the errors, warnings and notices of the given levels to `handler`, see
+    /// [`Context::set_error_handler`].
+    pub fn error_handler(
+        mut self,
+        levels: ErrorLevel,
+        handler: impl ErrorHandler + 'static,
+    ) -> Self {
+        self.context.set_error_handler(levels, handler);
+        self
+    }
+
+    /// Register a callback to be called when the context is initialised, see
+    /// [`Context::on_init`].
+    pub fn on_init<F: FnOnce(&mut Context<Sapi>) + 'static>(mut self, callback: F) -> Self {
+        self.context.on_init(callback);
+        self
+    }
+
+    /// Create the context. It is initialised when it is first used.
+    pub fn build(self) -> Context<'a, Sapi> {
+        self.context
+    }
+}
@@ -1,32 +1,72 @@
 use std::{
-    ffi::{c_char, CString},
+    cell::Cell,
+    ffi::{c_char, c_int, CString},
+    panic::{self, AssertUnwindSafe},
     ptr::{null, null_mut},
+    sync::atomic::{AtomicU64, Ordering},
 };
 
+use super::{
+    engine::{booted_ini_entries, BootConfig},
+    errors::ErrorHook,
+    interrupt::InterruptTarget,
+    Engine, InterruptHandle, Variables,
+};
 use crate::{
-    sapi::{
-        embedded::EmbeddedSapi,
-        raw::{get_partial_module_for_c, RawPhpSapi},
-    },
+    error::PhpError,
+    sapi::{embedded::EmbeddedSapi, raw::RawPhpSapi},
     sys::{
-        libphp_eval_stringl_ex, libphp_execute_simple_script, libphp_register_constant,
-        libphp_register_variable, libphp_zval_create_string, php_module_shutdown,
-        php_request_startup, php_rust_clear_server_context, php_rust_init, zend_call_function,
-        zend_execute_data, zend_fcall_info, zend_fcall_info_cache, zend_file_handle,
-        zend_function_entry, zend_internal_arg_info, zend_register_functions,
-        zend_stream_init_filename, zend_type, zval,
+        _zend_new_array, libphp_call_function, libphp_eval_stringl_ex,
+        libphp_eval_stringl_scoped_ex, libphp_execute_simple_script, libphp_execute_source,
+        libphp_exit_status, libphp_global_get, libphp_global_set, libphp_global_unset,
+        libphp_hash_str_update, libphp_register_constant, libphp_register_variable,
+        libphp_symbol_table, libphp_zval_create_string, php_request_shutdown, php_request_startup,
+        php_rust_clear_server_context, php_rust_set_argv, php_rust_set_tmp_server_ctx,
+        zend_array_destroy, zend_execute_data, zend_fcall_info, zend_fcall_info_cache,
+        zend_file_handle, zend_function_entry, zend_internal_arg_info, zend_register_functions,
+        zend_stream_init_filename, zend_type, zval, HT_MIN_SIZE,
     },
     value::Value,
 };
 
+/// Check if PHP source starts like a file (with an open tag or shebang line) rather than
+/// with bare code.
+pub(crate) fn has_open_tag(source: &str) -> bool {
+    source.starts_with("#!") || source.trim_start().starts_with("<?")
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static CURRENT_REQUEST_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Get the id of the request currently running on this thread, or 0 if there is none.
+pub(crate) fn current_request_id() -> u64 {
+    CURRENT_REQUEST_ID.get()
+}
+
 pub type FunctionImplementation = unsafe extern "C" fn(*mut zend_execute_data, *mut zval);
 
 pub struct Context<'a, Sapi: crate::sapi::raw::RawPhpSapi = EmbeddedSapi> {
-    initd: bool,
+    pub(super) initd: bool,
+    engine: Option<Engine<Sapi>>,
+    request_id: u64,
     on_init: Option<Box<dyn FnOnce(&mut Context<Sapi>)>>,
-    argc: i32,
     argv: Vec<String>,
+    // The arguments of the running request, which PHP points to.
+    request_argv: Vec<CString>,
+    request_argv_ptrs: Vec<*mut c_char>,
     bindings: Vec<Value>,
+    interrupt: Option<InterruptTarget>,
+    pub(super) error_hook: Option<Box<ErrorHook>>,
+    pub(super) ini: Vec<(String, String)>,
+    pub(super) boot: BootConfig,
+    // PHP bailed out of the code that ran last, which leaves the request unable to run
+    // more code. It is ended once the outermost call into the context returns.
+    aborted: bool,
+    // The number of calls into the context that are running, see `Context::enter`.
+    depth: u32,
     content: &'a mut Sapi::Context,
 }
 
@@ -35,11 +75,19 @@ impl<'a> Context<'a, EmbeddedSapi> {
     pub fn new() -> Self {
         Self {
             initd: false,
+            engine: None,
+            request_id: 0,
             on_init: None,
-            argc: 0,
             argv: Vec::new(),
+            request_argv: Vec::new(),
+            request_argv_ptrs: Vec::new(),
             bindings: Vec::new(),
-            // TODO: Free context again
+            interrupt: None,
+            error_hook: None,
+            ini: Vec::new(),
+            boot: BootConfig::default(),
+            aborted: false,
+            depth: 0,
             content: Box::leak(Box::new(())),
         }
     }
@@ -50,10 +98,19 @@ impl<'a, Sapi: RawPhpSapi> Context<'a, Sapi> {
     pub fn new_with_sapi(content: Box<Sapi::Context>) -> Self {
         Self {
             initd: false,
+            engine: None,
+            request_id: 0,
             on_init: None,
-            argc: 0,
             argv: Vec::new(),
+            request_argv: Vec::new(),
+            request_argv_ptrs: Vec::new(),
             bindings: Vec::new(),
+            interrupt: None,
+            error_hook: None,
+            ini: Vec::new(),
+            boot: BootConfig::default(),
+            aborted: false,
+            depth: 0,
             content: Box::leak(content),
         }
     }
@@ -64,6 +121,8 @@ impl<'a, Sapi: RawPhpSapi> Context<'a, Sapi> {
         let mut value = value.into();
         let var_name_cstr = CString::new(name).unwrap();
 
+        self.init();
+
         unsafe {
             libphp_register_variable(var_name_cstr.as_ptr(), value.as_mut_ptr());
         }
@@ -71,12 +130,57 @@ impl<'a, Sapi: RawPhpSapi> Context<'a, Sapi> {
         self.bindings.push(value);
     }
 
+    /// Get the value of a global variable, or `None` if it is not set.
+    pub fn global(&mut self, name: &str) -> Option<Value> {
+        self.init();
+
+        let mut retval = zval::default();
+        let found =
+            unsafe { libphp_global_get(name.as_ptr() as *const c_char, name.len(), &mut retval) };
+
+        found.then(|| Value::new(&retval))
+    }
+
+    /// Set a global variable, overwriting any existing value.
+    ///
+    /// Unlike [`Context::bind`], this also updates variables the running script has
+    /// already bound to, and the context keeps no reference to the value.
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        let mut value = value.into();
+
+        self.init();
+
+        unsafe {
+            libphp_global_set(
+                name.as_ptr() as *const c_char,
+                name.len(),
+                value.as_mut_ptr(),
+            );
+        }
+    }
+
+    /// Unset a global variable. Returns `false` if the variable did not exist.
+    pub fn unset_global(&mut self, name: &str) -> bool {
+        self.init();
+
+        unsafe { libphp_global_unset(name.as_ptr() as *const c_char, name.len()) }
+    }
+
+    /// Iterate over all global variables (`$GLOBALS`).
+    pub fn globals(&mut self) -> Variables<'_> {
+        self.init();
+
+        Variables::new(unsafe { &*libphp_symbol_table() })
+    }
+
     /// Define a constant in the PHP context.
     /// The constant will be available in the PHP context as a global constant.
     pub fn define(&mut self, name: &str, value: impl Into<Value>) {
         let mut value = value.into();
         let constant_name_cstr = CString::new(name).unwrap();
 
+        self.init();
+
         unsafe {
             libphp_register_constant(constant_name_cstr.as_ptr(), value.as_mut_ptr());
         }
@@ -117,119 +221,301 @@ impl<'a, Sapi: RawPhpSapi> Context<'a, Sapi> {
     }
 
     /// Specify the number of arguments to pass to the PHP context.
-    pub fn argc(&mut self, argc: i32) {
-        self.argc = argc;
-    }
+    #[deprecated(note = "`$argc` is the number of arguments passed to `argv`")]
+    pub fn argc(&mut self, _argc: i32) {}
 
-    /// Specify the arguments to pass to the PHP context.
+    /// Specify the arguments to pass to the PHP context, starting with the script name.
+    ///
+    /// Like with `php-cli`, PHP code sees them as `$argv`, `$argc` and `$_SERVER['argv']`.
+    /// They apply to requests started afterwards.
     pub fn argv(&mut self, argv: Vec<String>) {
         self.argv = argv;
     }
 
     /// Execute a PHP file.
-    pub fn execute_file(&mut self, file: &str, reset_global_ctx: bool) -> Value {
+    pub fn execute_file(&mut self, file: &str, reset_global_ctx: bool) -> Result<Value, PhpError> {
         let mut file_handle = zend_file_handle::default();
         let cstring = CString::new(file).unwrap();
 
         self.init();
+        let _span =
+            tracing::debug_span!("execute_file", file, request_id = self.request_id).entered();
 
-        unsafe {
-            zend_stream_init_filename(&mut file_handle, cstring.as_ptr());
-        }
+        self.checked(|_| {
+            unsafe {
+                zend_stream_init_filename(&mut file_handle, cstring.as_ptr());
+            }
 
-        let mut retval_ptr = zval::default();
+            let mut retval_ptr = zval::default();
 
-        unsafe {
-            libphp_execute_simple_script(&mut file_handle, &mut retval_ptr, reset_global_ctx);
-        }
+            unsafe {
+                libphp_execute_simple_script(&mut file_handle, &mut retval_ptr, reset_global_ctx);
+            }
 
-        Value::new(&retval_ptr)
+            Value::new(&retval_ptr)
+        })
     }
-    
+
+    /// Execute PHP source code as if it had been read from `filename`.
+    ///
+    /// The source is compiled like a file: it may start with `<?php` (or a shebang line),
+    /// contain any number of statements and `declare(strict_types=1)`, and `__FILE__`,
+    /// `__DIR__` and error messages refer to `filename`. Source without an open tag is
+    /// treated as PHP code rather than inline HTML.
+    pub fn execute_source(&mut self, source: &str, filename: &str) -> Result<Value, PhpError> {
+        self.init();
+        let _span = tracing::debug_span!(
+            "execute_source",
+            file = filename,
+            request_id = self.request_id
+        )
+        .entered();
+
+        let filename = CString::new(filename).unwrap();
+        let raw_code = !has_open_tag(source);
+
+        self.checked(|_| {
+            let mut retval_ptr = zval::default();
+
+            unsafe {
+                libphp_execute_source(
+                    source.as_ptr() as *const c_char,
+                    source.len(),
+                    filename.as_ptr(),
+                    raw_code,
+                    &mut retval_ptr,
+                    false,
+                );
+            }
+
+            Value::new(&retval_ptr)
+        })
+    }
+
     pub fn clear_bindings(&mut self) {
         self.bindings.clear();
     }
 
     /// Evaluate a PHP expression and get the result.
-    pub fn result_of(&mut self, expression: &str, clear_globals: bool) -> Value {
+    pub fn result_of(&mut self, expression: &str, clear_globals: bool) -> Result<Value, PhpError> {
+        let script_name = CString::new("eval'd code").unwrap();
+
+        self.checked(|ctx| {
+            let mut retval_ptr = zval::default();
+
+            unsafe {
+                libphp_eval_stringl_ex(
+                    expression.as_ptr() as *const c_char,
+                    expression.len(),
+                    &mut retval_ptr as *mut zval,
+                    script_name.as_ptr(),
+                    clear_globals,
+                );
+            }
+
+            ctx.bindings.clear();
+
+            Value::new(&retval_ptr)
+        })
+    }
+
+    /// Evaluate a PHP expression in a fresh local scope that only contains `vars`.
+    ///
+    /// Globals are not visible to the expression unless it asks for them (`global $x`,
+    /// `$GLOBALS`), and nothing it assigns leaks into the global symbol table.
+    pub fn eval_with<'v, V: Into<Value>>(
+        &mut self,
+        expression: &str,
+        vars: impl IntoIterator<Item = (&'v str, V)>,
+    ) -> Result<Value, PhpError> {
+        self.eval_with_locals(expression, vars)
+            .map(|(value, _)| value)
+    }
+
+    /// Like [`Context::eval_with`], but also return the final values of all variables in
+    /// the local scope, including any the expression assigned.
+    pub fn eval_with_locals<'v, V: Into<Value>>(
+        &mut self,
+        expression: &str,
+        vars: impl IntoIterator<Item = (&'v str, V)>,
+    ) -> Result<(Value, Vec<(String, Value)>), PhpError> {
         let script_name = CString::new("eval'd code").unwrap();
 
         self.init();
+        let _span = tracing::debug_span!("eval_with", request_id = self.request_id).entered();
+
+        self.checked(|_| {
+            let scope = unsafe { _zend_new_array(HT_MIN_SIZE) };
+
+            for (name, value) in vars {
+                let mut value = value.into();
+
+                unsafe {
+                    libphp_hash_str_update(
+                        scope,
+                        name.as_ptr() as *const c_char,
+                        name.len(),
+                        value.as_mut_ptr(),
+                    );
+                }
+            }
 
-        let mut retval_ptr = zval::default();
+            let mut retval_ptr = zval::default();
 
-        unsafe {
-            libphp_eval_stringl_ex(
-                expression.as_ptr() as *const c_char,
-                expression.len(),
-                &mut retval_ptr as *mut zval,
-                script_name.as_ptr(),
-                clear_globals,
-            );
-        }
+            unsafe {
+                libphp_eval_stringl_scoped_ex(
+                    expression.as_ptr() as *const c_char,
+                    expression.len(),
+                    &mut retval_ptr as *mut zval,
+                    script_name.as_ptr(),
+                    scope,
+                );
+            }
 
-        self.bindings.clear();
+            let locals = Variables::new(unsafe { &*scope }).collect();
+
+            unsafe {
+                zend_array_destroy(scope);
+            }
 
-        Value::new(&retval_ptr)
+            (Value::new(&retval_ptr), locals)
+        })
     }
 
     /// Call a PHP function with no arguments.
-    pub fn call(&mut self, name: &str) -> Value {
+    pub fn call(&mut self, name: &str) -> Result<Value, PhpError> {
         let name_cstring = CString::new(name).unwrap();
 
         self.init();
+        let _span =
+            tracing::debug_span!("call", function = name, request_id = self.request_id).entered();
 
-        let mut retval_ptr = zval::default();
+        self.checked(|_| {
+            let mut retval_ptr = zval::default();
 
-        let mut fcall = zend_fcall_info::default();
-        let mut fcall_cache = zend_fcall_info_cache::default();
+            let mut fcall = zend_fcall_info::default();
+            let mut fcall_cache = zend_fcall_info_cache::default();
 
-        unsafe {
-            libphp_zval_create_string(&mut fcall.function_name, name_cstring.as_ptr());
-        }
+            unsafe {
+                libphp_zval_create_string(&mut fcall.function_name, name_cstring.as_ptr());
+            }
 
-        fcall.param_count = 0;
-        fcall.object = null_mut();
-        fcall.size = std::mem::size_of::<zend_fcall_info>();
-        fcall.retval = &mut retval_ptr;
+            fcall.param_count = 0;
+            fcall.object = null_mut();
+            fcall.size = std::mem::size_of::<zend_fcall_info>();
+            fcall.retval = &mut retval_ptr;
 
-        unsafe {
-            zend_call_function(&mut fcall, &mut fcall_cache);
-        }
+            unsafe {
+                libphp_call_function(&mut fcall, &mut fcall_cache);
+            }
 
-        return Value::new(&retval_ptr);
+            Value::new(&retval_ptr)
+        })
     }
 
-    /// Call a PHP function with no arguments.
-    pub fn call_with(&mut self, name: &str, args: &[impl Into<Value> + Clone]) -> Value {
+    /// Call a PHP function with the given arguments.
+    pub fn call_with(
+        &mut self,
+        name: &str,
+        args: &[impl Into<Value> + Clone],
+    ) -> Result<Value, PhpError> {
         let name_cstring = CString::new(name).unwrap();
 
         self.init();
+        let _span =
+            tracing::debug_span!("call", function = name, request_id = self.request_id).entered();
+
+        self.checked(|_| {
+            // Convert the given arguments into a list of values.
+            let args = args
+                .iter()
+                .map(|arg| arg.clone().into())
+                .collect::<Vec<Value>>();
+            // PHP reads the parameters as an array of zvals, while every value is boxed on
+            // its own. The values keep ownership and release them after the call.
+            let mut params = args
+                .iter()
+                .map(|arg| unsafe { *arg.as_ptr() })
+                .collect::<Vec<zval>>();
+            let mut retval_ptr = zval::default();
+            let mut fcall = zend_fcall_info::default();
+            let mut fcall_cache = zend_fcall_info_cache::default();
+
+            unsafe {
+                libphp_zval_create_string(&mut fcall.function_name, name_cstring.as_ptr());
+            }
 
-        // Convert the given arguments into a list of values.
-        let mut args = args
-            .iter()
-            .map(|arg| arg.clone().into())
-            .collect::<Vec<Value>>();
-        let mut retval_ptr = zval::default();
-        let mut fcall = zend_fcall_info::default();
-        let mut fcall_cache = zend_fcall_info_cache::default();
+            fcall.param_count = args.len() as u32;
+            fcall.params = params.as_mut_ptr();
+            fcall.object = null_mut();
+            fcall.size = std::mem::size_of::<zend_fcall_info>();
+            fcall.retval = &mut retval_ptr;
 
-        unsafe {
-            libphp_zval_create_string(&mut fcall.function_name, name_cstring.as_ptr());
-        }
+            unsafe {
+                libphp_call_function(&mut fcall, &mut fcall_cache);
+            }
 
-        fcall.param_count = args.len() as u32;
-        fcall.params = args.first_mut().unwrap().as_mut_ptr();
-        fcall.object = null_mut();
-        fcall.size = std::mem::size_of::<zend_fcall_info>();
-        fcall.retval = &mut retval_ptr;
+            drop(args);
 
-        unsafe {
-            zend_call_function(&mut fcall, &mut fcall_cache);
+            Value::new(&retval_ptr)
+        })
+    }
+
+    /// Run `f`, failing with the fatal error PHP raised, the `exit()` it called or the
+    /// diagnostic the error handler escalated while it ran, if any.
+    pub(crate) fn checked<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> Result<T, PhpError> {
+        self.enter(|ctx| {
+            ctx.init();
+
+            // An earlier error of the same call left the VM unusable.
+            if ctx.aborted {
+                return Err(PhpError::Ended);
+            }
+
+            ctx.take_escalated();
+
+            let watch = PhpError::watch();
+            let value = f(ctx);
+            let escalated = ctx.take_escalated();
+            let result = watch.finish();
+
+            // PHP bails out of every error without unwinding the VM stack or the output
+            // layers, so the request must not run anything else. Only `exit()` unwinds.
+            if escalated.is_some()
+                || matches!(&result, Err(err) if !matches!(err, PhpError::Exit(_)))
+            {
+                ctx.aborted = true;
+            }
+
+            ctx.resume_panic();
+
+            match escalated {
+                Some(diagnostic) => Err(PhpError::Escalated(diagnostic)),
+                None => result.map(|_| value),
+            }
+        })
+    }
+
+    /// Run `f` as a call into the context, and end the request once the outermost call
+    /// returns if PHP bailed out of the code it ran.
+    ///
+    /// Calls that wrap others, like [`Context::capture_output`], still find the request
+    /// they started in when the code they wrap fails.
+    pub(super) fn enter<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.depth += 1;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)));
+        self.depth -= 1;
+
+        if self.depth == 0 && self.aborted {
+            self.close();
         }
 
-        return Value::new(&retval_ptr);
+        result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    /// Get the context the SAPI uses for the requests of this context.
+    pub fn sapi_context(&mut self) -> &mut Sapi::Context {
+        self.content
     }
 
     /// Register a callback to be called when the execution context is initialised.
@@ -245,52 +531,133 @@ impl<'a, Sapi: RawPhpSapi> Context<'a, Sapi> {
             return;
         }
 
+        if self.engine.is_none() {
+            self.engine = Some(Engine::boot_with_config(&self.boot));
+
+            // Settings PHP was booted with are already in effect.
+            let booted = booted_ini_entries();
+            self.ini.retain(|setting| !booted.contains(setting));
+        }
+
+        self.set_request_argv();
+
         unsafe {
-            php_rust_init(
-                get_partial_module_for_c::<Sapi>(),
+            php_rust_set_tmp_server_ctx(
                 self.content as *mut Sapi::Context as *mut std::ffi::c_void,
-                if self.argv.is_empty() {
-                    null_mut()
-                } else {
-                    self.argv.first().unwrap().as_ptr() as *mut i8
-                },
             );
         }
 
-        Sapi::on_before_request_init();
+        Sapi::on_before_request_init(self.content);
 
         unsafe {
             if php_request_startup() != 0 {
-                php_module_shutdown();
+                php_rust_clear_server_context();
+                php_rust_set_argv(0, null_mut());
                 panic!("Failed to start PHP request");
             }
         }
 
+        self.install_error_hook();
+        Sapi::on_request_start(self.content);
+
+        self.initd = true;
+        self.request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        CURRENT_REQUEST_ID.set(self.request_id);
+
+        for (name, value) in self.ini.clone() {
+            if !self.ini_set(&name, &value) {
+                tracing::warn!("Failed to set INI setting {} to {:?}", name, value);
+            }
+        }
+
         if let Some(callback) = self.on_init.take() {
             callback(self);
         }
+    }
 
-        self.initd = true;
+    /// Pass the arguments to the next request, like `php-cli` does.
+    fn set_request_argv(&mut self) {
+        self.request_argv = self
+            .argv
+            .iter()
+            .map(|arg| CString::new(arg.as_str()).unwrap())
+            .collect();
+
+        // PHP expects a NULL-terminated array, like the one passed to main().
+        self.request_argv_ptrs = self
+            .request_argv
+            .iter()
+            .map(|arg| arg.as_ptr() as *mut c_char)
+            .chain(std::iter::once(null_mut()))
+            .collect();
+
+        unsafe {
+            php_rust_set_argv(
+                self.request_argv.len() as c_int,
+                self.request_argv_ptrs.as_mut_ptr(),
+            );
+        }
+    }
+
+    /// Get the id of the PHP request this context runs, which is unique within the process.
+    ///
+    /// Returns 0 if the context has not been initialised yet.
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    /// Get the status `php-cli` would exit with after the code run so far: the one passed to
+    /// `exit()`, 255 after a fatal error, or 0.
+    pub fn exit_status(&mut self) -> i32 {
+        self.init();
+
+        unsafe { libphp_exit_status() }
+    }
+
+    /// Get a handle to interrupt the code running in this context from another thread.
+    ///
+    /// The handle applies to the current request only.
+    pub fn interrupt_handle(&mut self) -> InterruptHandle {
+        self.init();
+
+        InterruptHandle::new(self.interrupt.get_or_insert_with(InterruptHandle::target))
     }
 
     /// Close the execution context.
     ///
+    /// This shuts down the request (RSHUTDOWN). The engine stays booted, so a new context can
+    /// be created afterwards.
+    ///
     /// NOTE: This method does not need to be called manually. The execution context is automatically closed when Context is dropped.
     pub fn close(&mut self) {
+        // Bound values belong to the request's memory manager.
+        self.clear_bindings();
+
+        if let Some(target) = self.interrupt.take() {
+            InterruptHandle::detach(&target);
+        }
+
         if self.initd {
-            unsafe { Sapi::shutdown(std::ptr::null_mut()) };
+            unsafe {
+                php_request_shutdown(null_mut());
+                php_rust_set_argv(0, null_mut());
+            }
+            Self::uninstall_error_hook();
+            CURRENT_REQUEST_ID.set(0);
+            self.initd = false;
+            self.aborted = false;
+            self.request_id = 0;
         }
         unsafe {
             php_rust_clear_server_context();
         }
-        // Explicitly drop the leaked &mut SapiContext
-        drop(unsafe { Box::from_raw(self.content) });
     }
 }
 
 impl<Sapi: RawPhpSapi> Drop for Context<'_, Sapi> {
     fn drop(&mut self) {
-        self.clear_bindings();
         self.close();
+        // Explicitly drop the leaked &mut SapiContext
+        drop(unsafe { Box::from_raw(self.content) });
     }
 }
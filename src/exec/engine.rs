@@ -0,0 +1,262 @@
+use std::{
+    env,
+    ffi::{c_char, CStr, CString},
+    marker::PhantomData,
+    path::PathBuf,
+    ptr::{null, null_mut},
+    sync::Mutex,
+    thread::{self, ThreadId},
+};
+
+use crate::{
+    sapi::{
+        embedded::EmbeddedSapi,
+        raw::{get_partial_module_for_c, RawPhpSapi},
+    },
+    sys::{libphp_install_vm_hooks, php_rust_ini, php_rust_init},
+};
+
+use super::Context;
+
+struct Booted {
+    sapi: &'static CStr,
+    thread: ThreadId,
+    config: BootConfig,
+}
+
+/// How PHP reads its configuration when it boots. Afterwards INI settings can only be
+/// changed per request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct BootConfig {
+    /// Read this INI file or directory instead of searching for `php.ini`.
+    pub(crate) ini_file: Option<PathBuf>,
+    /// INI settings with a lower precedence than INI files.
+    pub(crate) ini_defaults: Vec<(String, String)>,
+    /// INI settings with a higher precedence than INI files, like `-d` for `php-cli`.
+    pub(crate) ini_entries: Vec<(String, String)>,
+    /// Don't read any INI file.
+    pub(crate) php_ini_ignore: bool,
+}
+
+// Extensions don't support being started again after a module shutdown, so once booted the
+// engine stays up until the process exits.
+static BOOTED: Mutex<Option<Booted>> = Mutex::new(None);
+
+/// Get the INI settings PHP was booted with, like `-d` for `php-cli`.
+pub(crate) fn booted_ini_entries() -> Vec<(String, String)> {
+    let booted = BOOTED.lock().unwrap_or_else(|err| err.into_inner());
+
+    booted
+        .as_ref()
+        .map_or_else(Vec::new, |booted| booted.config.ini_entries.clone())
+}
+
+/// Write INI settings in INI syntax, quoting values the way `php-cli` does for `-d`.
+fn ini_entries(settings: &[(String, String)]) -> String {
+    let mut entries = String::new();
+
+    for (name, value) in settings {
+        if value.starts_with(|c: char| !c.is_ascii_alphanumeric() && c != '"' && c != '\'') {
+            entries.push_str(&format!("{}=\"{}\"\n", name, value));
+        } else {
+            entries.push_str(&format!("{}={}\n", name, value));
+        }
+    }
+
+    entries
+}
+
+/// The interpreter globals of a thread other than the one that booted PHP.
+#[cfg(feature = "zts")]
+struct ThreadResources;
+
+#[cfg(feature = "zts")]
+impl ThreadResources {
+    fn attach() -> Self {
+        unsafe { crate::sys::php_rust_thread_attach() };
+        Self
+    }
+}
+
+#[cfg(feature = "zts")]
+impl Drop for ThreadResources {
+    fn drop(&mut self) {
+        unsafe { crate::sys::php_rust_thread_detach() };
+    }
+}
+
+#[cfg(feature = "zts")]
+thread_local! {
+    static THREAD_RESOURCES: ThreadResources = ThreadResources::attach();
+}
+
+/// A booted PHP engine.
+///
+/// Booting runs the module startup (MINIT) of PHP and all extensions once. Requests can
+/// then be run one after another with [`Engine::request`], each getting a fresh request
+/// startup and shutdown (RINIT/RSHUTDOWN), which is much cheaper than booting again.
+///
+/// PHP is booted at most once per process: booting again returns a handle to the running
+/// engine, so engines and [`Context`]s can be created and dropped freely. The engine is never
+/// shut down (MSHUTDOWN), and all handles must use the same SAPI.
+///
+/// With the `zts` feature, the engine can be shared between threads and every thread runs
+/// its own requests concurrently, with interpreter globals allocated the first time it
+/// starts one. [`Context`]s stay on the thread that created them. Without `zts`, PHP can
+/// only be used from the thread that booted it.
+pub struct Engine<Sapi: RawPhpSapi = EmbeddedSapi> {
+    _sapi: PhantomData<*const Sapi>,
+}
+
+#[cfg(feature = "zts")]
+unsafe impl<Sapi: RawPhpSapi> Send for Engine<Sapi> {}
+
+#[cfg(feature = "zts")]
+unsafe impl<Sapi: RawPhpSapi> Sync for Engine<Sapi> {}
+
+impl Engine<EmbeddedSapi> {
+    /// Boot the PHP engine with the embedded SAPI.
+    pub fn boot() -> Self {
+        Self::boot_with_sapi()
+    }
+}
+
+impl<Sapi: RawPhpSapi> Engine<Sapi> {
+    /// Boot the PHP engine with a custom SAPI.
+    pub fn boot_with_sapi() -> Self {
+        Self::boot_with_config(&BootConfig::default())
+    }
+
+    pub(crate) fn boot_with_config(config: &BootConfig) -> Self {
+        let name = unsafe { CStr::from_ptr(Sapi::name) };
+        let mut booted = BOOTED.lock().unwrap_or_else(|err| err.into_inner());
+
+        if let Some(booted) = &*booted {
+            if config.ini_file != booted.config.ini_file
+                || config.ini_defaults != booted.config.ini_defaults
+                || config.php_ini_ignore != booted.config.php_ini_ignore
+            {
+                tracing::warn!(
+                    "PHP is already booted, so its INI files and defaults are not changed"
+                );
+            }
+
+            let late = config
+                .ini_entries
+                .iter()
+                .filter(|setting| !booted.config.ini_entries.contains(setting))
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+
+            if !late.is_empty() {
+                tracing::warn!(
+                    "PHP is already booted, so the INI settings {} are only applied once each \
+                     request has started, too late for settings PHP reads at startup",
+                    late.join(", ")
+                );
+            }
+        }
+
+        match &*booted {
+            Some(booted) if booted.sapi != name => {
+                panic!("PHP is already booted with the {:?} SAPI", booted.sapi)
+            }
+            #[cfg(feature = "zts")]
+            Some(booted) => {
+                if booted.thread != thread::current().id() {
+                    THREAD_RESOURCES.with(|_| ());
+                }
+            }
+            #[cfg(not(feature = "zts"))]
+            Some(booted) => {
+                if booted.thread != thread::current().id() {
+                    panic!("PHP was booted on another thread, which requires the zts feature");
+                }
+            }
+            None => {
+                let path_override = config
+                    .ini_file
+                    .as_ref()
+                    .map(|path| CString::new(path.to_string_lossy().into_owned()).unwrap());
+
+                let mut defaults = Vec::new();
+                for (name, value) in &config.ini_defaults {
+                    defaults.extend_from_slice(
+                        CString::new(name.as_str()).unwrap().as_bytes_with_nul(),
+                    );
+                    defaults.extend_from_slice(
+                        CString::new(value.as_str()).unwrap().as_bytes_with_nul(),
+                    );
+                }
+                defaults.push(0);
+
+                let entries = CString::new(ini_entries(&config.ini_entries)).unwrap();
+
+                // PHP keeps the location for PHP_BINARY, so it must live as long as the
+                // process. Like with php-cli, it is the running executable.
+                let executable_location = env::current_exe()
+                    .ok()
+                    .and_then(|exe| CString::new(exe.to_string_lossy().into_owned()).ok())
+                    .map_or(null_mut(), CString::into_raw);
+
+                let ini = php_rust_ini {
+                    path_override: path_override.as_ref().map_or(null(), |path| path.as_ptr()),
+                    defaults: defaults.as_ptr() as *const c_char,
+                    entries: entries.as_ptr(),
+                    ignore: config.php_ini_ignore,
+                };
+
+                let result = unsafe {
+                    php_rust_init(
+                        get_partial_module_for_c::<Sapi>(),
+                        null_mut(),
+                        executable_location,
+                        &ini,
+                    )
+                };
+
+                if result != 0 {
+                    panic!("Failed to start PHP module");
+                }
+
+                unsafe { libphp_install_vm_hooks() };
+
+                *booted = Some(Booted {
+                    sapi: name,
+                    thread: thread::current().id(),
+                    config: config.clone(),
+                });
+            }
+        }
+
+        Self { _sapi: PhantomData }
+    }
+
+    /// Run a request with a default SAPI context.
+    ///
+    /// The request is started before `f` is called and shut down once it returns, so nothing
+    /// the code running in it defines or allocates outlives it. [`Value`](crate::value::Value)s
+    /// belong to the request, so `f` must be `'static`, which keeps it from handing them out
+    /// through a borrowed variable, and its result must be `Send`, which `Value`s are not.
+    /// Return an [`OwnedValue`](crate::value::OwnedValue) instead.
+    pub fn request<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Context<'_, Sapi>) -> R + 'static,
+        Sapi::Context: Default,
+    {
+        self.request_with(Box::default(), f)
+    }
+
+    /// Run a request with the given SAPI context.
+    pub fn request_with<R, F>(&self, content: Box<Sapi::Context>, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Context<'_, Sapi>) -> R + 'static,
+    {
+        let mut context = Context::new_with_sapi(content);
+        context.init();
+
+        f(&mut context)
+    }
+}
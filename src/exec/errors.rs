@@ -0,0 +1,168 @@
+use std::{
+    any::Any,
+    ffi::{c_char, c_int, c_void},
+    panic::{self, AssertUnwindSafe},
+};
+
+use tracing::Level;
+
+use crate::{
+    diagnostic::{ErrorAction, ErrorHandler, ErrorLevel, PhpDiagnostic, Severity},
+    sapi::raw::RawPhpSapi,
+    sys::{
+        libphp_error_reporting, php_rust_error_action,
+        php_rust_error_action_PHP_RUST_ERROR_ESCALATE,
+        php_rust_error_action_PHP_RUST_ERROR_HANDLED, php_rust_error_action_PHP_RUST_ERROR_PASS,
+        php_rust_error_hook, php_rust_set_error_hook,
+    },
+};
+
+use super::{context::current_request_id, Context};
+
+/// An [`ErrorHandler`] that emits diagnostics as `tracing` events instead of displaying
+/// them, with fields for the file, line and request id.
+///
+/// Fatal errors are emitted at the `ERROR` level, warnings at `WARN`, and notices and
+/// deprecations at `INFO`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingErrorHandler;
+
+impl ErrorHandler for TracingErrorHandler {
+    fn handle(&mut self, diagnostic: &PhpDiagnostic) -> ErrorAction {
+        let request_id = current_request_id();
+        let file = diagnostic.file.as_deref();
+
+        macro_rules! emit {
+            ($level:expr) => {
+                tracing::event!(
+                    $level,
+                    request_id,
+                    file,
+                    line = diagnostic.line,
+                    kind = diagnostic.level.label(),
+                    "{}",
+                    diagnostic.message
+                )
+            };
+        }
+
+        match diagnostic.severity() {
+            Severity::Error => emit!(Level::ERROR),
+            Severity::Warning => emit!(Level::WARN),
+            Severity::Notice | Severity::Deprecated => emit!(Level::INFO),
+        }
+
+        ErrorAction::Handled
+    }
+}
+
+/// The error handler of a context, which PHP calls into while its request runs.
+pub(super) struct ErrorHook {
+    levels: ErrorLevel,
+    handler: Box<dyn ErrorHandler>,
+    // The first diagnostic escalated since the code started.
+    escalated: Option<PhpDiagnostic>,
+    // The panic of the handler, which can't unwind through PHP.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+unsafe extern "C" fn handle_error(
+    data: *mut c_void,
+    level: c_int,
+    file: *const c_char,
+    file_len: usize,
+    line: u32,
+    message: *const c_char,
+    message_len: usize,
+) -> php_rust_error_action {
+    let hook = &mut *(data as *mut ErrorHook);
+
+    // Respect `error_reporting` and the @ operator, like PHP's own handlers do.
+    if hook.panic.is_some()
+        || !hook.levels.intersects(ErrorLevel(level))
+        || !ErrorLevel(libphp_error_reporting()).intersects(ErrorLevel(level))
+    {
+        return php_rust_error_action_PHP_RUST_ERROR_PASS;
+    }
+
+    let diagnostic = PhpDiagnostic::from_raw(level, file, file_len, line, message, message_len);
+
+    let action = panic::catch_unwind(AssertUnwindSafe(|| hook.handler.handle(&diagnostic)));
+
+    match action.unwrap_or_else(|payload| {
+        hook.panic = Some(payload);
+        ErrorAction::Pass
+    }) {
+        ErrorAction::Pass => php_rust_error_action_PHP_RUST_ERROR_PASS,
+        ErrorAction::Handled => php_rust_error_action_PHP_RUST_ERROR_HANDLED,
+        ErrorAction::Escalate => {
+            hook.escalated.get_or_insert(diagnostic);
+            php_rust_error_action_PHP_RUST_ERROR_ESCALATE
+        }
+    }
+}
+
+impl<Sapi: RawPhpSapi> Context<'_, Sapi> {
+    /// Pass the errors, warnings and notices of the given levels to `handler` instead of
+    /// displaying them, replacing the previous handler.
+    ///
+    /// Diagnostics that `error_reporting` excludes, or that are silenced with `@`, are not
+    /// passed to the handler.
+    ///
+    /// If the handler panics, PHP handles the diagnostic and the ones after it itself, and
+    /// the panic resumes once the code returns.
+    pub fn set_error_handler(&mut self, levels: ErrorLevel, handler: impl ErrorHandler + 'static) {
+        self.error_hook = Some(Box::new(ErrorHook {
+            levels,
+            handler: Box::new(handler),
+            escalated: None,
+            panic: None,
+        }));
+
+        if self.initd {
+            self.install_error_hook();
+        }
+    }
+
+    /// Let PHP display and log all diagnostics again.
+    pub fn clear_error_handler(&mut self) {
+        if self.initd {
+            Self::uninstall_error_hook();
+        }
+
+        self.error_hook = None;
+    }
+
+    /// Route the errors of the running request to the error handler, if there is one.
+    pub(super) fn install_error_hook(&mut self) {
+        if let Some(hook) = &mut self.error_hook {
+            unsafe {
+                php_rust_set_error_hook(php_rust_error_hook {
+                    callback: Some(handle_error),
+                    data: &mut **hook as *mut ErrorHook as *mut c_void,
+                });
+            }
+        }
+    }
+
+    pub(super) fn uninstall_error_hook() {
+        unsafe {
+            php_rust_set_error_hook(php_rust_error_hook {
+                callback: None,
+                data: std::ptr::null_mut(),
+            });
+        }
+    }
+
+    /// Take the diagnostic the error handler escalated, if any.
+    pub(super) fn take_escalated(&mut self) -> Option<PhpDiagnostic> {
+        self.error_hook.as_mut()?.escalated.take()
+    }
+
+    /// Resume the panic of the error handler, if it panicked.
+    pub(super) fn resume_panic(&mut self) {
+        if let Some(payload) = self.error_hook.as_mut().and_then(|hook| hook.panic.take()) {
+            panic::resume_unwind(payload);
+        }
+    }
+}
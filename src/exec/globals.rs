@@ -0,0 +1,62 @@
+use std::ptr::NonNull;
+
+use crate::{
+    sys::{
+        libphp_zval_copy_deref, zend_hash_get_current_data_ex, zend_hash_get_current_key_type_ex,
+        zend_hash_get_current_key_zval_ex, zend_hash_move_forward_ex, zval, HashTable,
+        HASH_KEY_NON_EXISTENT,
+    },
+    value::Value,
+};
+
+/// Iterator over the variables of a PHP symbol table, such as `$GLOBALS`.
+///
+/// Slots that point at the compiled variables of a running script (`IS_INDIRECT`) are
+/// followed, and variables that have been unset are skipped.
+pub struct Variables<'a> {
+    ptr: &'a HashTable,
+    pos: u32,
+}
+
+impl<'a> Variables<'a> {
+    pub fn new(ptr: &'a HashTable) -> Self {
+        Self { ptr, pos: 0 }
+    }
+
+    fn table(&self) -> *mut HashTable {
+        self.ptr as *const HashTable as *mut HashTable
+    }
+}
+
+impl Iterator for Variables<'_> {
+    type Item = (String, Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let key_type =
+                unsafe { zend_hash_get_current_key_type_ex(self.table(), &mut self.pos) };
+
+            if key_type == HASH_KEY_NON_EXISTENT {
+                return None;
+            }
+
+            let mut key = zval::default();
+            let mut value = zval::default();
+
+            let defined = unsafe {
+                zend_hash_get_current_key_zval_ex(self.table(), &mut key, &self.pos);
+
+                let data = NonNull::new(zend_hash_get_current_data_ex(self.table(), &mut self.pos));
+                zend_hash_move_forward_ex(self.table(), &mut self.pos);
+
+                data.is_some_and(|data| libphp_zval_copy_deref(&mut value, data.as_ptr()))
+            };
+
+            let key = Value::new(&key);
+
+            if defined {
+                return Some((key.to_string(), Value::new(&value)));
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::{ffi::c_char, ptr::null, slice};
+
+use crate::{
+    sapi::raw::RawPhpSapi,
+    sys::{libphp_ini_get, libphp_ini_set},
+};
+
+use super::Context;
+
+impl<Sapi: RawPhpSapi> Context<'_, Sapi> {
+    /// Get the current value of an INI setting, like `ini_get()`.
+    ///
+    /// Returns `None` if there is no such setting, and an empty string if it has no value.
+    pub fn ini_get(&mut self, name: &str) -> Option<String> {
+        self.init();
+
+        let mut value: *const c_char = null();
+        let mut value_len = 0;
+
+        unsafe {
+            if !libphp_ini_get(
+                name.as_ptr() as *const c_char,
+                name.len(),
+                &mut value,
+                &mut value_len,
+            ) {
+                return None;
+            }
+
+            if value.is_null() {
+                return Some(String::new());
+            }
+
+            let bytes = slice::from_raw_parts(value as *const u8, value_len);
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+    }
+
+    /// Change an INI setting until the end of the current request, like `ini_set()`.
+    ///
+    /// Unlike `ini_set()`, this can also change settings that are normally only allowed in
+    /// `php.ini`, although some of those are only read when PHP boots. Returns `false` if
+    /// there is no such setting or it rejected the value.
+    pub fn ini_set(&mut self, name: &str, value: &str) -> bool {
+        self.init();
+
+        unsafe {
+            libphp_ini_set(
+                name.as_ptr() as *const c_char,
+                name.len(),
+                value.as_ptr() as *const c_char,
+                value.len(),
+            )
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::sys::{libphp_vm_clear_interrupt, libphp_vm_flags, libphp_vm_raise_interrupt};
+
+use super::limits::VmFlags;
+
+/// The VM an [`InterruptHandle`] interrupts, which is only set while its request runs.
+pub(crate) type InterruptTarget = Arc<Mutex<Option<VmFlags>>>;
+
+fn lock(target: &InterruptTarget) -> MutexGuard<'_, Option<VmFlags>> {
+    target.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Stops the PHP code running in a [`Context`](super::Context) from any thread.
+///
+/// Interrupted code fails with [`PhpError::Interrupted`](crate::error::PhpError::Interrupted).
+/// Like time limits, interrupts are checked between PHP instructions, so a blocking call
+/// such as `sleep()` is not interrupted until it returns, and they end the request: the
+/// context starts a new one for the code it runs next. The handle applies to the ended
+/// request only.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    target: InterruptTarget,
+}
+
+impl InterruptHandle {
+    /// Interrupt the code running in the context.
+    ///
+    /// If no code is running, the next code the request runs is interrupted instead. Once
+    /// the request is closed this does nothing.
+    pub fn interrupt(&self) {
+        if let Some(flags) = &*lock(&self.target) {
+            unsafe { libphp_vm_raise_interrupt(flags.0) };
+        }
+    }
+
+    /// Create a target for the VM of the current thread.
+    pub(crate) fn target() -> InterruptTarget {
+        Arc::new(Mutex::new(Some(VmFlags(unsafe { libphp_vm_flags() }))))
+    }
+
+    pub(crate) fn new(target: &InterruptTarget) -> Self {
+        Self {
+            target: target.clone(),
+        }
+    }
+
+    /// Stop handles from interrupting the VM, and forget any pending interrupt.
+    pub(crate) fn detach(target: &InterruptTarget) {
+        if let Some(flags) = lock(target).take() {
+            unsafe { libphp_vm_clear_interrupt(flags.0) };
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    ptr::null_mut,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::PhpError,
+    sapi::raw::RawPhpSapi,
+    sys::{
+        libphp_restore_memory_limit, libphp_set_memory_limit, libphp_set_time_limit,
+        libphp_vm_clear_timeout, libphp_vm_flags, libphp_vm_raise_timeout, zend_string,
+    },
+};
+
+use super::{
+    timer::{self, Expire},
+    Context,
+};
+
+/// Limits on the resources PHP code may use, see [`Context::with_limits`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Stop the code once this much real time has passed.
+    pub wall_time: Option<Duration>,
+    /// Stop the code once it has used this much CPU time, like `max_execution_time`. PHP
+    /// only supports whole seconds, so this is rounded up.
+    pub cpu_time: Option<Duration>,
+    /// The number of bytes the request may allocate. This sets `memory_limit` while the
+    /// code runs.
+    pub memory: Option<usize>,
+}
+
+pub(crate) struct VmFlags(pub(crate) libphp_vm_flags);
+
+// The flags are atomics, which the VM expects to be set from other threads.
+unsafe impl Send for VmFlags {}
+
+/// Raises a timeout in the VM of the thread that started it once its deadline has passed.
+struct Watchdog {
+    // The VM to stop, until the watchdog is stopped itself.
+    flags: Mutex<Option<VmFlags>>,
+}
+
+impl Expire for Watchdog {
+    fn expire(&self) {
+        if let Some(flags) = &*self.flags.lock().unwrap_or_else(|err| err.into_inner()) {
+            unsafe { libphp_vm_raise_timeout(flags.0) };
+        }
+    }
+}
+
+impl Watchdog {
+    fn start(timeout: Duration) -> Arc<Self> {
+        let watchdog = Arc::new(Self {
+            flags: Mutex::new(Some(VmFlags(unsafe { libphp_vm_flags() }))),
+        });
+        let expire: Arc<dyn Expire> = watchdog.clone();
+
+        timer::add(Instant::now() + timeout, Arc::downgrade(&expire));
+        watchdog
+    }
+
+    fn stop(&self) {
+        // Once this returns the timer can't raise the timeout anymore.
+        *self.flags.lock().unwrap_or_else(|err| err.into_inner()) = None;
+
+        // The timeout may have been raised after the code finished.
+        unsafe { libphp_vm_clear_timeout(libphp_vm_flags()) };
+    }
+}
+
+/// Restores the limits that were in place before [`Context::with_limits`], even if the
+/// code panicked.
+struct Restore {
+    watchdog: Option<Arc<Watchdog>>,
+    time_limit: Option<i64>,
+    // The previous value of `memory_limit`.
+    memory_limit: Option<*mut zend_string>,
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.stop();
+        }
+
+        if let Some(seconds) = self.time_limit {
+            unsafe { libphp_set_time_limit(seconds) };
+        }
+
+        if let Some(previous) = self.memory_limit {
+            unsafe { libphp_restore_memory_limit(previous) };
+        }
+    }
+}
+
+impl<Sapi: RawPhpSapi> Context<'_, Sapi> {
+    /// Run `f` with limits on the time and memory the PHP code it runs may use.
+    ///
+    /// Code that exceeds a time limit fails with [`PhpError::Timeout`], and code that
+    /// exceeds the memory limit fails with [`PhpError::OutOfMemory`]. Like any error, this
+    /// ends the request once `with_limits` returns, see [`PhpError::Fatal`]. If the request
+    /// already uses more memory than allowed, `f` does not run.
+    ///
+    /// Time limits are checked between PHP instructions, so a blocking call such as
+    /// `sleep()` is not interrupted until it returns.
+    pub fn with_limits<R>(
+        &mut self,
+        limits: Limits,
+        f: impl FnOnce(&mut Self) -> Result<R, PhpError>,
+    ) -> Result<R, PhpError> {
+        self.enter(|ctx| ctx.limited(limits, f))
+    }
+
+    fn limited<R>(
+        &mut self,
+        limits: Limits,
+        f: impl FnOnce(&mut Self) -> Result<R, PhpError>,
+    ) -> Result<R, PhpError> {
+        self.init();
+
+        let mut restore = Restore {
+            watchdog: None,
+            time_limit: None,
+            memory_limit: None,
+        };
+
+        if let Some(memory) = limits.memory {
+            let mut previous = null_mut();
+
+            if !unsafe { libphp_set_memory_limit(memory, &mut previous) } {
+                return Err(PhpError::OutOfMemory);
+            }
+            restore.memory_limit = Some(previous);
+        }
+
+        if let Some(cpu_time) = limits.cpu_time {
+            let seconds = cpu_time.as_secs_f64().ceil().max(1.0) as i64;
+            restore.time_limit = Some(unsafe { libphp_set_time_limit(seconds) });
+        }
+
+        if let Some(wall_time) = limits.wall_time {
+            restore.watchdog = Some(Watchdog::start(wall_time));
+        }
+
+        let result = f(self);
+        drop(restore);
+        result
+    }
+}
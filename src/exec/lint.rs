@@ -0,0 +1,111 @@
+use std::ffi::{c_char, c_int, c_void, CString};
+
+use crate::{
+    diagnostic::{PhpDiagnostic, Severity},
+    sapi::raw::RawPhpSapi,
+    sys::{
+        libphp_lint_file, libphp_lint_source, php_rust_error_action,
+        php_rust_error_action_PHP_RUST_ERROR_HANDLED, php_rust_error_hook, php_rust_set_error_hook,
+        ZEND_RESULT_CODE_SUCCESS,
+    },
+};
+
+use super::{context::has_open_tag, Context};
+
+/// The outcome of compiling a file or source without executing it.
+#[derive(Debug, Clone)]
+pub struct LintReport {
+    compiled: bool,
+    pub diagnostics: Vec<PhpDiagnostic>,
+}
+
+impl LintReport {
+    /// Check if the code compiled without errors.
+    ///
+    /// Warnings and deprecations do not make the code fail to compile.
+    pub fn is_ok(&self) -> bool {
+        self.compiled
+            && self
+                .diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.severity() < Severity::Error)
+    }
+
+    /// Get the diagnostics that prevented the code from compiling.
+    pub fn errors(&self) -> impl Iterator<Item = &PhpDiagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity() == Severity::Error)
+    }
+}
+
+unsafe extern "C" fn collect_diagnostic(
+    data: *mut c_void,
+    level: c_int,
+    file: *const c_char,
+    file_len: usize,
+    line: u32,
+    message: *const c_char,
+    message_len: usize,
+) -> php_rust_error_action {
+    let diagnostics = &mut *(data as *mut Vec<PhpDiagnostic>);
+    diagnostics.push(PhpDiagnostic::from_raw(
+        level,
+        file,
+        file_len,
+        line,
+        message,
+        message_len,
+    ));
+
+    php_rust_error_action_PHP_RUST_ERROR_HANDLED
+}
+
+impl<Sapi: RawPhpSapi> Context<'_, Sapi> {
+    /// Check a PHP file for syntax errors without executing it, like `php -l`.
+    pub fn lint_file(&mut self, file: &str) -> LintReport {
+        let file = CString::new(file).unwrap();
+
+        self.lint(|| unsafe { libphp_lint_file(file.as_ptr()) })
+    }
+
+    /// Check PHP source code for syntax errors without executing it.
+    ///
+    /// Diagnostics refer to the source as "Standard input code", as `php -l` does.
+    pub fn lint_source(&mut self, source: &str) -> LintReport {
+        let raw_code = !has_open_tag(source);
+
+        self.lint(|| unsafe {
+            libphp_lint_source(
+                source.as_ptr() as *const c_char,
+                source.len(),
+                c"Standard input code".as_ptr(),
+                raw_code,
+            )
+        })
+    }
+
+    fn lint(&mut self, compile: impl FnOnce() -> i32) -> LintReport {
+        self.init();
+
+        let mut diagnostics = Vec::new();
+
+        let previous = unsafe {
+            php_rust_set_error_hook(php_rust_error_hook {
+                callback: Some(collect_diagnostic),
+                data: &mut diagnostics as *mut Vec<PhpDiagnostic> as *mut c_void,
+            })
+        };
+
+        let compiled = compile() == ZEND_RESULT_CODE_SUCCESS;
+
+        unsafe {
+            php_rust_set_error_hook(previous);
+        }
+
+        LintReport {
+            compiled,
+            diagnostics,
+        }
+    }
+}
@@ -1,3 +1,26 @@
+mod builder;
 mod context;
+mod engine;
+mod errors;
+mod globals;
+mod ini;
+mod interrupt;
+mod limits;
+mod lint;
+mod output;
+#[cfg(feature = "zts")]
+mod pool;
+mod script;
+mod timer;
 
+pub use builder::*;
 pub use context::*;
+pub use engine::*;
+pub use errors::*;
+pub use globals::*;
+pub use interrupt::*;
+pub use limits::*;
+pub use lint::*;
+#[cfg(feature = "zts")]
+pub use pool::*;
+pub use script::*;
@@ -0,0 +1,88 @@
+use std::{
+    ffi::{c_char, c_void},
+    io::Write,
+};
+
+use crate::{
+    sapi::raw::RawPhpSapi,
+    sys::{
+        libphp_output_capture_end, libphp_output_capture_start, libphp_output_start_sink,
+        libphp_zval_get_stringl, zval, zval_ptr_dtor,
+    },
+};
+
+use super::Context;
+
+type Sink = Box<dyn Write>;
+
+unsafe extern "C" fn sink_write(data: *mut c_void, buf: *const c_char, len: usize) -> usize {
+    let sink = &mut *(data as *mut Sink);
+    let bytes = std::slice::from_raw_parts(buf as *const u8, len);
+
+    match sink.write_all(bytes) {
+        Ok(()) => len,
+        Err(err) => {
+            tracing::debug!("Failed to write to output sink: {}", err);
+            0
+        }
+    }
+}
+
+unsafe extern "C" fn sink_drop(data: *mut c_void) {
+    let mut sink = Box::from_raw(data as *mut Sink);
+
+    if let Err(err) = sink.flush() {
+        tracing::debug!("Failed to flush output sink: {}", err);
+    }
+}
+
+impl<Sapi: RawPhpSapi> Context<'_, Sapi> {
+    /// Run `f` and collect everything the PHP code it runs writes to the output.
+    ///
+    /// This works like wrapping the code in `ob_start()` and `ob_get_clean()`: captures can be
+    /// nested, and output buffers the script leaves open are flushed into the capture.
+    pub fn capture_output<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> (R, Vec<u8>) {
+        self.enter(|ctx| {
+            ctx.init();
+
+            let level = unsafe { libphp_output_capture_start() };
+            let result = f(ctx);
+
+            let mut contents = zval::default();
+            let output = unsafe {
+                libphp_output_capture_end(level, &mut contents);
+
+                let mut len = 0;
+                let buf = libphp_zval_get_stringl(&mut contents, &mut len);
+                let output = std::slice::from_raw_parts(buf as *const u8, len).to_vec();
+
+                zval_ptr_dtor(&mut contents);
+                output
+            };
+
+            (result, output)
+        })
+    }
+
+    /// Send all further output of this context to `sink` instead of the SAPI.
+    ///
+    /// Output buffers started by the script still apply and are written to the sink once
+    /// flushed. The script cannot remove the sink; it is flushed and dropped when the
+    /// context is closed.
+    pub fn set_output_sink(&mut self, sink: impl Write + 'static) {
+        self.init();
+
+        let sink: Box<Sink> = Box::new(Box::new(sink));
+        let started = unsafe {
+            libphp_output_start_sink(
+                Some(sink_write),
+                Some(sink_drop),
+                Box::into_raw(sink) as *mut c_void,
+            )
+        };
+
+        if !started {
+            tracing::debug!("Failed to start output sink");
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    future::Future,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::{Context as TaskContext, Poll, Waker},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::PhpError,
+    sapi::{embedded::EmbeddedSapi, raw::RawPhpSapi},
+    value::OwnedValue,
+};
+
+use super::{
+    timer::{self, Expire},
+    Context, Engine, InterruptHandle,
+};
+
+type Task<Sapi> = Box<dyn FnOnce(&Engine<Sapi>) + Send>;
+
+/// Why a job submitted to a [`PhpPool`] did not produce a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+    /// The job did not finish before its timeout.
+    Timeout,
+    /// The job panicked.
+    Panicked,
+    /// The pool was shut down before the job was queued.
+    Closed,
+}
+
+impl Display for PoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "PHP job timed out"),
+            Self::Panicked => write!(f, "PHP job panicked"),
+            Self::Closed => write!(f, "PHP pool is shut down"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+struct Queue<Sapi: RawPhpSapi> {
+    tasks: VecDeque<Task<Sapi>>,
+    capacity: usize,
+    closed: bool,
+    // Jobs waiting for room in the queue, by their id.
+    submitters: Vec<(usize, Waker)>,
+}
+
+struct Shared<Sapi: RawPhpSapi> {
+    queue: Mutex<Queue<Sapi>>,
+    available: Condvar,
+}
+
+/// Where the result of a job ends up, shared by the job future, the worker and the timer.
+struct Slot<R> {
+    state: Mutex<SlotState<R>>,
+}
+
+struct SlotState<R> {
+    result: Option<Result<R, PoolError>>,
+    waker: Option<Waker>,
+    done: bool,
+    // Stops the job once it is running.
+    interrupt: Option<InterruptHandle>,
+}
+
+impl<R> Slot<R> {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(SlotState {
+                result: None,
+                waker: None,
+                done: false,
+                interrupt: None,
+            }),
+        }
+    }
+
+    /// Check if the job no longer needs to run, because it timed out or was dropped.
+    fn is_done(&self) -> bool {
+        lock(&self.state).done
+    }
+
+    /// Remember how to stop the running job, stopping it right away if it has timed out.
+    fn set_interrupt(&self, interrupt: InterruptHandle) {
+        let mut state = lock(&self.state);
+
+        if state.done {
+            interrupt.interrupt();
+        } else {
+            state.interrupt = Some(interrupt);
+        }
+    }
+
+    fn complete(&self, result: Result<R, PoolError>) {
+        let mut state = lock(&self.state);
+
+        if !state.done {
+            state.done = true;
+            state.result = Some(result);
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn poll(&self, waker: &Waker) -> Poll<Result<R, PoolError>> {
+        let mut state = lock(&self.state);
+
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(waker.clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn cancel(&self) {
+        lock(&self.state).done = true;
+    }
+}
+
+impl<R: Send> Expire for Slot<R> {
+    fn expire(&self) {
+        self.complete(Err(PoolError::Timeout));
+
+        if let Some(interrupt) = lock(&self.state).interrupt.take() {
+            interrupt.interrupt();
+        }
+    }
+}
+
+/// A pool of threads that each run PHP requests on a shared [`Engine`].
+///
+/// Jobs are submitted as futures, which don't depend on any particular async runtime. A
+/// job is queued when its future is first polled; if the queue is full, the future waits
+/// for room instead of blocking the runtime. Results must be `Send`, so PHP values are
+/// returned as [`OwnedValue`]s.
+///
+/// Dropping the pool waits for all queued jobs to finish.
+pub struct PhpPool<Sapi: RawPhpSapi + 'static = EmbeddedSapi> {
+    shared: Arc<Shared<Sapi>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl PhpPool<EmbeddedSapi> {
+    /// Start a pool of `threads` PHP workers, which queues at most one job per worker.
+    pub fn new(threads: usize) -> Self {
+        Self::new_with_sapi(threads, threads)
+    }
+}
+
+impl<Sapi: RawPhpSapi + 'static> PhpPool<Sapi> {
+    /// Start a pool of `threads` workers with a custom SAPI, which queues at most
+    /// `queue_capacity` jobs before submitting more has to wait.
+    pub fn new_with_sapi(threads: usize, queue_capacity: usize) -> Self {
+        // Boot on the calling thread, so a failure to start PHP panics here.
+        Engine::<Sapi>::boot_with_sapi();
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                tasks: VecDeque::new(),
+                capacity: queue_capacity.max(1),
+                closed: false,
+                submitters: Vec::new(),
+            }),
+            available: Condvar::new(),
+        });
+
+        let workers = (0..threads.max(1))
+            .map(|index| {
+                let shared = shared.clone();
+
+                thread::Builder::new()
+                    .name(format!("php-worker-{}", index))
+                    .spawn(move || Self::work(&shared))
+                    .expect("Failed to spawn PHP worker thread")
+            })
+            .collect();
+
+        Self { shared, workers }
+    }
+
+    fn work(shared: &Shared<Sapi>) {
+        let engine = Engine::<Sapi>::boot_with_sapi();
+
+        loop {
+            let task = {
+                let mut queue = lock(&shared.queue);
+
+                loop {
+                    if let Some(task) = queue.tasks.pop_front() {
+                        queue
+                            .submitters
+                            .drain(..)
+                            .for_each(|(_, waker)| waker.wake());
+                        break task;
+                    }
+                    if queue.closed {
+                        return;
+                    }
+                    queue = shared
+                        .available
+                        .wait(queue)
+                        .unwrap_or_else(|err| err.into_inner());
+                }
+            };
+
+            task(&engine);
+        }
+    }
+
+    /// Run `f` in a new request with the given SAPI context.
+    pub fn run_with<R, F>(&self, content: Box<Sapi::Context>, f: F) -> Job<R, Sapi>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Context<'_, Sapi>) -> R + Send + 'static,
+        Sapi::Context: Send,
+    {
+        let slot = Arc::new(Slot::new());
+        let task_slot = slot.clone();
+
+        let task: Task<Sapi> = Box::new(move |engine| {
+            if task_slot.is_done() {
+                return;
+            }
+
+            let request_slot = task_slot.clone();
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                engine.request_with(content, move |ctx| {
+                    request_slot.set_interrupt(ctx.interrupt_handle());
+                    f(ctx)
+                })
+            }));
+            task_slot.complete(result.map_err(|_| PoolError::Panicked));
+        });
+
+        Job {
+            shared: self.shared.clone(),
+            task: Some(task),
+            slot,
+        }
+    }
+}
+
+impl<Sapi: RawPhpSapi + 'static> PhpPool<Sapi>
+where
+    Sapi::Context: Default + Send,
+{
+    /// Run `f` in a new request with a default SAPI context.
+    pub fn run<R, F>(&self, f: F) -> Job<R, Sapi>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Context<'_, Sapi>) -> R + Send + 'static,
+    {
+        self.run_with(Box::default(), f)
+    }
+
+    /// Execute a PHP file in a new request.
+    pub fn execute_file(&self, file: impl Into<String>) -> Job<Result<OwnedValue, PhpError>, Sapi> {
+        let file = file.into();
+
+        self.run(move |ctx| ctx.execute_file(&file, false).map(OwnedValue::from))
+    }
+
+    /// Call a PHP function in a new request.
+    pub fn call(
+        &self,
+        name: impl Into<String>,
+        args: Vec<OwnedValue>,
+    ) -> Job<Result<OwnedValue, PhpError>, Sapi> {
+        let name = name.into();
+
+        self.run(move |ctx| {
+            if args.is_empty() {
+                ctx.call(&name)
+            } else {
+                ctx.call_with(&name, &args)
+            }
+            .map(OwnedValue::from)
+        })
+    }
+}
+
+impl<Sapi: RawPhpSapi + 'static> Drop for PhpPool<Sapi> {
+    fn drop(&mut self) {
+        {
+            let mut queue = lock(&self.shared.queue);
+            queue.closed = true;
+            queue
+                .submitters
+                .drain(..)
+                .for_each(|(_, waker)| waker.wake());
+        }
+        self.shared.available.notify_all();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A job submitted to a [`PhpPool`], which resolves to the job's result.
+///
+/// The job is queued when the future is first polled. Dropping the future before the job
+/// started cancels it.
+pub struct Job<R, Sapi: RawPhpSapi + 'static = EmbeddedSapi> {
+    shared: Arc<Shared<Sapi>>,
+    task: Option<Task<Sapi>>,
+    slot: Arc<Slot<R>>,
+}
+
+impl<R: Send + 'static, Sapi: RawPhpSapi + 'static> Job<R, Sapi> {
+    /// Fail the job with [`PoolError::Timeout`] if it has not finished within `timeout`,
+    /// counting from now.
+    ///
+    /// A job that times out while it is queued does not run. A job that is already running
+    /// is interrupted, like with [`InterruptHandle::interrupt`], and its result is discarded.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        let slot: Arc<dyn Expire> = self.slot.clone();
+
+        timer::add(Instant::now() + timeout, Arc::downgrade(&slot));
+        self
+    }
+}
+
+impl<R, Sapi: RawPhpSapi + 'static> Job<R, Sapi> {
+    /// Identify the job while it waits for room in the queue.
+    fn id(&self) -> usize {
+        Arc::as_ptr(&self.slot) as *const () as usize
+    }
+}
+
+impl<R, Sapi: RawPhpSapi + 'static> Future for Job<R, Sapi> {
+    type Output = Result<R, PoolError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Some(task) = this.task.take() {
+            if this.slot.is_done() {
+                return this.slot.poll(cx.waker());
+            }
+
+            let mut queue = lock(&this.shared.queue);
+
+            if queue.closed {
+                return Poll::Ready(Err(PoolError::Closed));
+            }
+
+            let id = this.id();
+
+            if queue.tasks.len() >= queue.capacity {
+                match queue
+                    .submitters
+                    .iter_mut()
+                    .find(|(submitter, _)| *submitter == id)
+                {
+                    Some((_, waker)) => waker.clone_from(cx.waker()),
+                    None => queue.submitters.push((id, cx.waker().clone())),
+                }
+                this.task = Some(task);
+                return Poll::Pending;
+            }
+
+            queue.submitters.retain(|(submitter, _)| *submitter != id);
+            queue.tasks.push_back(task);
+            drop(queue);
+            this.shared.available.notify_one();
+        }
+
+        this.slot.poll(cx.waker())
+    }
+}
+
+impl<R, Sapi: RawPhpSapi + 'static> Drop for Job<R, Sapi> {
+    fn drop(&mut self) {
+        self.slot.cancel();
+
+        // A job that never got into the queue may still be waiting for room.
+        if self.task.is_some() {
+            let id = self.id();
+
+            lock(&self.shared.queue)
+                .submitters
+                .retain(|(submitter, _)| *submitter != id);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    ffi::{c_char, CString},
+    ptr::NonNull,
+};
+
+use crate::{
+    error::PhpError,
+    sapi::raw::RawPhpSapi,
+    sys::{
+        libphp_compile_file, libphp_compile_source, libphp_destroy_op_array,
+        libphp_execute_op_array, zend_op_array, zval,
+    },
+    value::Value,
+};
+
+use super::{
+    context::{current_request_id, has_open_tag},
+    Context,
+};
+
+/// A compiled PHP script that can be executed repeatedly without being compiled again.
+///
+/// The compiled code lives in the memory of the request it was compiled in, so a script
+/// can only be run by the context that compiled it, until that request ends.
+pub struct CompiledScript {
+    op_array: NonNull<zend_op_array>,
+    request_id: u64,
+}
+
+impl CompiledScript {
+    /// Execute the script and get its return value.
+    ///
+    /// Fails with [`PhpError::Ended`] if the request the script was compiled in has ended,
+    /// for example because an error stopped the code it ran.
+    pub fn run<Sapi: RawPhpSapi>(&self, ctx: &mut Context<Sapi>) -> Result<Value, PhpError> {
+        ctx.checked(|ctx| {
+            // The request may have been ended after the script was compiled, and a new one
+            // started to run it.
+            if ctx.request_id() != self.request_id {
+                return None;
+            }
+
+            let mut retval_ptr = zval::default();
+
+            unsafe {
+                libphp_execute_op_array(self.op_array.as_ptr(), &mut retval_ptr, false);
+            }
+
+            Some(Value::new(&retval_ptr))
+        })
+        .and_then(|value| value.ok_or(PhpError::Ended))
+    }
+}
+
+impl Drop for CompiledScript {
+    fn drop(&mut self) {
+        // Once the request is over its memory, and the script with it, has been freed.
+        if current_request_id() == self.request_id {
+            unsafe { libphp_destroy_op_array(self.op_array.as_ptr()) };
+        }
+    }
+}
+
+impl<Sapi: RawPhpSapi> Context<'_, Sapi> {
+    /// Compile a PHP file without executing it.
+    ///
+    /// Returns `None` if the file could not be compiled, after PHP reported the error.
+    pub fn compile_file(&mut self, file: &str) -> Option<CompiledScript> {
+        let file = CString::new(file).unwrap();
+
+        self.init();
+
+        let op_array = unsafe { libphp_compile_file(file.as_ptr()) };
+
+        NonNull::new(op_array).map(|op_array| CompiledScript {
+            op_array,
+            request_id: self.request_id(),
+        })
+    }
+
+    /// Compile PHP source code as if it had been read from `filename`, without executing
+    /// it. See [`Context::execute_source`].
+    ///
+    /// Returns `None` if the source could not be compiled, after PHP reported the error.
+    pub fn compile_source(&mut self, source: &str, filename: &str) -> Option<CompiledScript> {
+        let filename = CString::new(filename).unwrap();
+        let raw_code = !has_open_tag(source);
+
+        self.init();
+
+        let op_array = unsafe {
+            libphp_compile_source(
+                source.as_ptr() as *const c_char,
+                source.len(),
+                filename.as_ptr(),
+                raw_code,
+            )
+        };
+
+        NonNull::new(op_array).map(|op_array| CompiledScript {
+            op_array,
+            request_id: self.request_id(),
+        })
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    sync::{Condvar, Mutex, MutexGuard, Once, Weak},
+    thread,
+    time::Instant,
+};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Something that happens once a deadline passes, see [`add`].
+pub(crate) trait Expire: Send + Sync {
+    fn expire(&self);
+}
+
+/// Expires items once their deadline has passed, on a thread shared by every pool and
+/// time limit of the process.
+struct Timer {
+    deadlines: Mutex<Vec<(Instant, Weak<dyn Expire>)>>,
+    changed: Condvar,
+}
+
+static TIMER: Timer = Timer {
+    deadlines: Mutex::new(Vec::new()),
+    changed: Condvar::new(),
+};
+
+static STARTED: Once = Once::new();
+
+/// Expire `item` at `deadline`, unless it has been dropped by then.
+pub(crate) fn add(deadline: Instant, item: Weak<dyn Expire>) {
+    STARTED.call_once(|| {
+        thread::Builder::new()
+            .name("php-timer".to_string())
+            .spawn(|| TIMER.run())
+            .expect("Failed to spawn PHP timer thread");
+    });
+
+    lock(&TIMER.deadlines).push((deadline, item));
+    TIMER.changed.notify_one();
+}
+
+impl Timer {
+    fn run(&self) {
+        let mut deadlines = lock(&self.deadlines);
+
+        loop {
+            let now = Instant::now();
+
+            deadlines.retain(|(deadline, item)| {
+                if *deadline > now {
+                    return item.strong_count() > 0;
+                }
+                if let Some(item) = item.upgrade() {
+                    item.expire();
+                }
+                false
+            });
+
+            deadlines = match deadlines.iter().map(|(deadline, _)| *deadline).min() {
+                Some(next) => {
+                    self.changed
+                        .wait_timeout(deadlines, next - now)
+                        .unwrap_or_else(|err| err.into_inner())
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(deadlines)
+                    .unwrap_or_else(|err| err.into_inner()),
+            };
+        }
+    }
+}
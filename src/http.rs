@@ -0,0 +1,408 @@
+//! Run PHP applications as a [`Service`] for the types of the `http` crate, so they can be
+//! mounted in hyper, axum or any other tower-based server.
+//!
+//! Requests are run on the worker threads of a [`PhpPool`], by a front controller script
+//! such as a framework's `index.php`. The response body is streamed as PHP produces it.
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    future::{poll_fn, Future},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::{Context as TaskContext, Poll, Waker},
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes};
+use http::{header::HOST, HeaderName, HeaderValue, Request, Response, StatusCode, Version};
+use http_body::{Body, Frame};
+use tower_service::Service;
+
+use crate::{
+    error::PhpError,
+    exec::{PhpPool, PoolError},
+    sapi::http::{HttpExchange, HttpRequest, HttpResponse, HttpSapi, ResponseSink},
+};
+
+/// How much of the body PHP may produce before it waits for the client to read it.
+const MAX_BUFFERED: usize = 64 * 1024;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The response of a running request, passed from the PHP worker to the body.
+struct Stream {
+    state: Mutex<StreamState>,
+    // Signalled when the body has been read, or is no longer wanted.
+    drained: Condvar,
+}
+
+struct StreamState {
+    head: Option<HttpResponse>,
+    chunks: VecDeque<Bytes>,
+    buffered: usize,
+    // The request has ended, and no more of the response will follow.
+    finished: bool,
+    // The response or its body was dropped.
+    abandoned: bool,
+    waker: Option<Waker>,
+}
+
+impl Stream {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(StreamState {
+                head: None,
+                chunks: VecDeque::new(),
+                buffered: 0,
+                finished: false,
+                abandoned: false,
+                waker: None,
+            }),
+            drained: Condvar::new(),
+        })
+    }
+
+    fn wake(state: &mut StreamState) {
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Wait for the status and headers, which are `None` if the request ended without them.
+    fn poll_head(&self, cx: &mut TaskContext<'_>) -> Poll<Option<HttpResponse>> {
+        let mut state = lock(&self.state);
+
+        if let Some(head) = state.head.take() {
+            return Poll::Ready(Some(head));
+        }
+        if state.finished {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn abandon(&self) {
+        let mut state = lock(&self.state);
+
+        state.abandoned = true;
+        state.chunks.clear();
+        self.drained.notify_all();
+    }
+}
+
+/// Passes the response from [`HttpSapi`] to the stream.
+struct StreamSink(Arc<Stream>);
+
+impl ResponseSink for StreamSink {
+    fn send_head(&mut self, response: &HttpResponse) {
+        let mut state = lock(&self.0.state);
+
+        state.head = Some(response.clone());
+        Stream::wake(&mut state);
+    }
+
+    fn write_body(&mut self, bytes: &[u8]) {
+        let mut state = lock(&self.0.state);
+
+        while state.buffered >= MAX_BUFFERED && !state.abandoned {
+            state = self
+                .0
+                .drained
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+
+        if !state.abandoned {
+            state.buffered += bytes.len();
+            state.chunks.push_back(Bytes::copy_from_slice(bytes));
+            Stream::wake(&mut state);
+        }
+    }
+}
+
+impl Drop for StreamSink {
+    fn drop(&mut self) {
+        let mut state = lock(&self.0.state);
+
+        state.finished = true;
+        Stream::wake(&mut state);
+    }
+}
+
+/// The body of a response produced by a [`PhpService`], streamed while PHP runs.
+pub struct PhpBody {
+    stream: Option<Arc<Stream>>,
+}
+
+impl PhpBody {
+    fn empty() -> Self {
+        Self { stream: None }
+    }
+}
+
+impl Body for PhpBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        let Some(stream) = &self.stream else {
+            return Poll::Ready(None);
+        };
+        let mut state = lock(&stream.state);
+
+        if let Some(chunk) = state.chunks.pop_front() {
+            state.buffered -= chunk.len();
+            stream.drained.notify_all();
+            return Poll::Ready(Some(Ok(Frame::data(chunk))));
+        }
+        if state.finished {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.stream.as_ref().is_none_or(|stream| {
+            let state = lock(&stream.state);
+            state.finished && state.chunks.is_empty()
+        })
+    }
+}
+
+impl Drop for PhpBody {
+    fn drop(&mut self) {
+        if let Some(stream) = &self.stream {
+            stream.abandon();
+        }
+    }
+}
+
+/// Abandons the stream if the response future is dropped before it has a body.
+struct AbandonGuard(Option<Arc<Stream>>);
+
+impl Drop for AbandonGuard {
+    fn drop(&mut self) {
+        if let Some(stream) = self.0.take() {
+            stream.abandon();
+        }
+    }
+}
+
+/// A [`Service`] that runs every request with a front controller script.
+///
+/// The script gets the request through `$_SERVER`, `$_GET`, `$_POST`, `$_COOKIE` and
+/// `php://input`, as it would behind a web server. A `SocketAddr` extension on the request
+/// is used as the client address.
+///
+/// Errors are turned into responses, so the service never fails: `400` if the request
+/// body could not be read, `503` if the pool is shut down, `504` if the request timed out
+/// before PHP responded, and `500` otherwise.
+#[derive(Clone)]
+pub struct PhpService {
+    pool: Arc<PhpPool<HttpSapi>>,
+    document_root: String,
+    script_name: String,
+    timeout: Option<Duration>,
+}
+
+impl PhpService {
+    /// Handle requests with the script at `script_name` in `document_root`, like
+    /// `/index.php`.
+    pub fn new(
+        pool: Arc<PhpPool<HttpSapi>>,
+        document_root: impl Into<String>,
+        script_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            pool,
+            document_root: document_root.into(),
+            script_name: script_name.into(),
+            timeout: None,
+        }
+    }
+
+    /// Interrupt requests that run longer than `timeout`, see
+    /// [`Job::timeout`](crate::exec::Job::timeout).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Read the whole body of a request, which PHP expects up front.
+async fn collect_body<B: Body>(body: B) -> Result<Vec<u8>, B::Error> {
+    let mut body = Box::pin(body);
+    let mut bytes = Vec::new();
+
+    while let Some(frame) = poll_fn(|cx| body.as_mut().poll_frame(cx)).await {
+        if let Ok(mut data) = frame?.into_data() {
+            while data.has_remaining() {
+                let chunk = data.chunk();
+                let len = chunk.len();
+
+                bytes.extend_from_slice(chunk);
+                data.advance(len);
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn protocol(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_2 => "HTTP/2.0",
+        Version::HTTP_3 => "HTTP/3.0",
+        _ => "HTTP/1.1",
+    }
+}
+
+fn error_response(status: StatusCode) -> Response<PhpBody> {
+    let mut response = Response::new(PhpBody::empty());
+    *response.status_mut() = status;
+    response
+}
+
+impl<B> Service<Request<B>> for PhpService
+where
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: std::fmt::Display,
+{
+    type Response = Response<PhpBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<PhpBody>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Infallible>> {
+        // The pool queues jobs itself, waiting for room when it is full.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let service = self.clone();
+
+        Box::pin(async move { Ok(service.handle(request).await) })
+    }
+}
+
+impl PhpService {
+    async fn handle<B>(self, request: Request<B>) -> Response<PhpBody>
+    where
+        B: Body,
+        B::Error: std::fmt::Display,
+    {
+        let (parts, body) = request.into_parts();
+
+        let body = match collect_body(body).await {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::debug!("Failed to read request body: {}", err);
+                return error_response(StatusCode::BAD_REQUEST);
+            }
+        };
+
+        let uri = parts
+            .uri
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str());
+
+        let mut php_request = HttpRequest::new(parts.method.as_str(), uri)
+            .script(self.document_root, self.script_name)
+            .body(body);
+
+        php_request.protocol = protocol(parts.version).to_string();
+        php_request.https = parts.uri.scheme() == Some(&http::uri::Scheme::HTTPS);
+        php_request.remote_addr = parts.extensions.get::<SocketAddr>().copied();
+
+        // HTTP/2 requests carry the host in the URI instead of a header.
+        if let (None, Some(authority)) = (parts.headers.get(HOST), parts.uri.authority()) {
+            php_request
+                .headers
+                .push(("Host".to_string(), authority.to_string()));
+        }
+
+        for (name, value) in &parts.headers {
+            php_request.headers.push((
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            ));
+        }
+
+        let stream = Stream::new();
+        let mut guard = AbandonGuard(Some(stream.clone()));
+        let exchange = HttpExchange::with_sink(php_request, StreamSink(stream.clone()));
+
+        let mut job = self.pool.run_with(Box::new(exchange), |ctx| {
+            let script = ctx.sapi_context().request.script_filename.clone();
+
+            match ctx.execute_file(&script, false).map(drop) {
+                Ok(()) | Err(PhpError::Exit(_)) => {}
+                Err(err) => tracing::warn!("PHP request failed: {}", err),
+            }
+        });
+
+        if let Some(timeout) = self.timeout {
+            job = job.timeout(timeout);
+        }
+
+        // The headers are sent before the request ends, so the job finishing first means
+        // it never ran or was stopped.
+        let head = poll_fn(|cx| {
+            if let Poll::Ready(head) = stream.poll_head(cx) {
+                return Poll::Ready(head.ok_or(None));
+            }
+
+            match Pin::new(&mut job).poll(cx) {
+                Poll::Ready(Ok(())) => Poll::Ready(Err(None)),
+                Poll::Ready(Err(err)) => Poll::Ready(Err(Some(err))),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await;
+
+        let head = match head {
+            Ok(head) => head,
+            Err(err) => {
+                return error_response(match err {
+                    Some(PoolError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+                    Some(PoolError::Closed) => StatusCode::SERVICE_UNAVAILABLE,
+                    Some(PoolError::Panicked) | None => StatusCode::INTERNAL_SERVER_ERROR,
+                });
+            }
+        };
+
+        let mut response = Response::new(PhpBody {
+            stream: guard.0.take(),
+        });
+
+        *response.status_mut() =
+            StatusCode::from_u16(head.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        for (name, value) in head.headers {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    response.headers_mut().append(name, value);
+                }
+                _ => tracing::debug!("Dropping invalid response header {}", name),
+            }
+        }
+
+        response
+    }
+}
@@ -2,7 +2,13 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+pub mod diagnostic;
+pub mod error;
 pub mod exec;
+#[cfg(feature = "http")]
+pub mod http;
 pub mod sys;
 pub mod value;
 pub mod sapi;
+#[cfg(unix)]
+pub mod supervisor;
@@ -16,7 +16,9 @@
 
 #include "sapi/embed/php_embed.h"
 #include "rust-sapi.h"
+#include "wrapper.h"
 #include "ext/standard/php_standard.h"
+#include "ext/standard/php_fopen_wrappers.h"
 #include "ext/standard/dl_arginfo.h"
 
 #ifdef PHP_WIN32
@@ -40,10 +42,78 @@ static const zend_function_entry additional_functions[] = {
 	ZEND_FE(dl, arginfo_dl)
 		ZEND_FE_END};
 
-// Global var that contains a pointer to the server context
-void *global_server_context;
+// Pointer to the server context of the request starting on this thread
+static ZEND_TLS void *global_server_context;
 
-EMBED_SAPI_API int php_rust_init(struct partial_sapi_module_struct module, void *server_context, char* executable_location)
+// INI defaults of the module being started, see php_rust_ini_defaults
+static const char *ini_defaults;
+
+/* Add the INI defaults to the configuration before any INI file is read, so
+ * files can override them. */
+static void php_rust_ini_defaults(HashTable *configuration_hash)
+{
+	const char *name = ini_defaults;
+	zval value;
+
+	while (name && *name) {
+		size_t name_len = strlen(name);
+		const char *entry = name + name_len + 1;
+		size_t entry_len = strlen(entry);
+
+		ZVAL_NEW_STR(&value, zend_string_init(entry, entry_len, 1));
+		zend_hash_str_update(configuration_hash, name, name_len, &value);
+
+		name = entry + entry_len + 1;
+	}
+}
+
+static void (*original_error_cb)(int type, zend_string *error_filename, const uint32_t error_lineno, zend_string *message);
+static ZEND_TLS struct php_rust_error_hook error_hook;
+
+static void php_rust_error_cb(int orig_type, zend_string *error_filename, const uint32_t error_lineno, zend_string *message)
+{
+	enum php_rust_error_action action = PHP_RUST_ERROR_PASS;
+	zend_uchar display_errors;
+	bool log_errors;
+
+	libphp_note_error(orig_type);
+
+	if (error_hook.callback) {
+		action = error_hook.callback(error_hook.data, orig_type & E_ALL,
+			error_filename ? ZSTR_VAL(error_filename) : NULL, error_filename ? ZSTR_LEN(error_filename) : 0,
+			error_lineno, ZSTR_VAL(message), ZSTR_LEN(message));
+	}
+
+	if (action == PHP_RUST_ERROR_PASS) {
+		original_error_cb(orig_type, error_filename, error_lineno, message);
+		return;
+	}
+
+	/* Handled: keep PHP's bookkeeping, but don't print or log anything. The
+	 * original callback bails out on fatal errors, so restore on the way out. */
+	display_errors = PG(display_errors);
+	log_errors = PG(log_errors);
+	PG(display_errors) = 0;
+	PG(log_errors) = 0;
+
+	zend_try {
+		original_error_cb(orig_type, error_filename, error_lineno, message);
+	} zend_catch {
+		PG(display_errors) = display_errors;
+		PG(log_errors) = log_errors;
+		zend_bailout();
+	} zend_end_try();
+
+	PG(display_errors) = display_errors;
+	PG(log_errors) = log_errors;
+
+	/* Without a zend_try to catch it, bailing out would exit the process. */
+	if (action == PHP_RUST_ERROR_ESCALATE && EG(bailout)) {
+		zend_bailout();
+	}
+}
+
+EMBED_SAPI_API int php_rust_init(struct partial_sapi_module_struct module, void *server_context, char* executable_location, const struct php_rust_ini *ini)
 {
 #if defined(SIGPIPE) && defined(SIG_IGN)
 	signal(SIGPIPE, SIG_IGN); /* ignore SIGPIPE in standalone mode so
@@ -81,10 +151,9 @@ EMBED_SAPI_API int php_rust_init(struct partial_sapi_module_struct module, void
 
 		php_error,
 
-		/*module.header_handler,
-		module.send_headers,*/
-		NULL,
+		/*module.header_handler,*/
 		NULL,
+		module.send_headers,
 		module.send_header,
 
 		module.read_post,
@@ -133,6 +202,31 @@ EMBED_SAPI_API int php_rust_init(struct partial_sapi_module_struct module, void
 	 */
 	php_rust_module.ini_entries = HARDCODED_INI;
 
+	if (ini)
+	{
+		if (ini->path_override)
+		{
+			php_rust_module.php_ini_path_override = strdup(ini->path_override);
+		}
+		php_rust_module.php_ini_ignore = ini->ignore;
+
+		ini_defaults = ini->defaults;
+		php_rust_module.ini_defaults = php_rust_ini_defaults;
+
+		/* Like php-cli does for -d, append the entries to the hard-coded ones so
+		 * they override those too. The module lives as long as the process. */
+		if (ini->entries && *ini->entries)
+		{
+			size_t hardcoded_len = sizeof(HARDCODED_INI) - 2;
+			size_t entries_len = strlen(ini->entries);
+			char *ini_entries = malloc(hardcoded_len + entries_len + 1);
+
+			memcpy(ini_entries, HARDCODED_INI, hardcoded_len);
+			memcpy(ini_entries + hardcoded_len, ini->entries, entries_len + 1);
+			php_rust_module.ini_entries = ini_entries;
+		}
+	}
+
 	/* SAPI-provided functions. */
 	php_rust_module.additional_functions = additional_functions;
 
@@ -144,9 +238,20 @@ EMBED_SAPI_API int php_rust_init(struct partial_sapi_module_struct module, void
 	/* Module initialization (MINIT) */
 	if (php_rust_module.startup(&php_rust_module) == FAILURE)
 	{
+		ini_defaults = NULL;
 		return FAILURE;
 	}
 
+	/* The configuration has been read, and the defaults belong to the caller. */
+	ini_defaults = NULL;
+
+	/* Route errors through the per-thread hook (see php_rust_set_error_hook). */
+	if (zend_error_cb != php_rust_error_cb)
+	{
+		original_error_cb = zend_error_cb;
+		zend_error_cb = php_rust_error_cb;
+	}
+
 	/* Do not chdir to the script's directory. This is akin to calling the CGI
 	 * SAPI with '-C'.
 	 */
@@ -158,7 +263,26 @@ EMBED_SAPI_API int php_rust_init(struct partial_sapi_module_struct module, void
 void php_rust_clear_server_context()
 {
 	global_server_context = NULL;
-	//SG(server_context) = NULL;
+	SG(server_context) = NULL;
+
+	/* The request info points to strings owned by the request that ended. */
+	SG(request_info).request_method = NULL;
+	SG(request_info).request_uri = NULL;
+	SG(request_info).query_string = NULL;
+	SG(request_info).content_type = NULL;
+	SG(request_info).content_length = 0;
+	SG(request_info).path_translated = NULL;
+	SG(request_info).cookie_data = NULL;
+	SG(sapi_headers).http_response_code = 0;
+}
+
+/* Set the context for the next request. sapi_activate() only reads the body
+ * and cookies of requests that already have a server context, so it is set
+ * right away as well. */
+void php_rust_set_tmp_server_ctx(void *server_context)
+{
+	global_server_context = server_context;
+	SG(server_context) = server_context;
 }
 
 void php_rust_set_server_context()
@@ -166,6 +290,185 @@ void php_rust_set_server_context()
 	SG(server_context) = global_server_context;
 }
 
+/* Output can be written before the request is activated (e.g. startup
+ * errors), so fall back to the context passed to php_rust_init. */
+void *php_rust_get_server_context()
+{
+	return SG(server_context) ? SG(server_context) : global_server_context;
+}
+
+void php_rust_thread_attach()
+{
+#ifdef ZTS
+	/* Allocate this thread's globals */
+	(void)ts_resource(0);
+# ifdef PHP_WIN32
+	ZEND_TSRMLS_CACHE_UPDATE();
+# endif
+#endif
+}
+
+void php_rust_thread_detach()
+{
+#ifdef ZTS
+	ts_free_thread();
+#endif
+}
+
 sapi_request_info* php_rust_get_request_info() {
 	return &SG(request_info);
-}
\ No newline at end of file
+}
+
+/* Set the arguments of the next request, which PHP exposes as $argv, $argc and
+ * $_SERVER['argv'] when register_argc_argv is on. `argv` must stay valid until
+ * the request is shut down. */
+void php_rust_set_argv(int argc, char **argv)
+{
+	SG(request_info).argc = argc;
+	SG(request_info).argv = argv;
+}
+
+/* The script the arguments of the request start with, like the script file
+ * php-cli runs, or NULL without arguments. */
+const char *php_rust_script_path()
+{
+	if (SG(request_info).argc > 0) {
+		return SG(request_info).argv[0];
+	}
+	return NULL;
+}
+
+/* Describe the HTTP request of the next request, which PHP uses for $_GET,
+ * $_POST, $_COOKIE, php://input and the authentication variables. The strings
+ * must stay valid until the request is shut down. */
+void php_rust_set_request_info(const struct php_rust_request_info *info)
+{
+	SG(request_info).request_method = info->method;
+	SG(request_info).request_uri = (char *) info->uri;
+	SG(request_info).query_string = (char *) info->query_string;
+	SG(request_info).content_type = info->content_type;
+	SG(request_info).content_length = info->content_length;
+	SG(request_info).path_translated = (char *) info->path_translated;
+
+	/* sapi_activate() leaves the response code to the SAPI, which makes
+	 * http_response_code() return false until it is set. */
+	SG(sapi_headers).http_response_code = 200;
+
+	/* Sets (or clears) auth_user, auth_password and auth_digest, which are
+	 * freed when the request is shut down. */
+	php_handle_auth_data(info->authorization);
+}
+
+struct php_rust_error_hook php_rust_set_error_hook(struct php_rust_error_hook hook)
+{
+	struct php_rust_error_hook previous = error_hook;
+
+	error_hook = hook;
+	return previous;
+}
+
+static ZEND_TLS struct php_rust_stdio stdio;
+
+static ssize_t php_rust_stdio_write(php_stream *stream, const char *buf, size_t count)
+{
+	return stdio.write((int) (uintptr_t) stream->abstract, buf, count);
+}
+
+static ssize_t php_rust_stdio_read(php_stream *stream, char *buf, size_t count)
+{
+	ssize_t read = stdio.read((int) (uintptr_t) stream->abstract, buf, count);
+
+	if (read == 0) {
+		stream->eof = 1;
+	}
+	return read;
+}
+
+static int php_rust_stdio_close(php_stream *stream, int close_handle)
+{
+	/* The Rust side owns the streams. */
+	return 0;
+}
+
+static int php_rust_stdio_flush(php_stream *stream)
+{
+	return stdio.flush((int) (uintptr_t) stream->abstract);
+}
+
+static const php_stream_ops php_rust_stdio_ops = {
+	.write = php_rust_stdio_write,
+	.read = php_rust_stdio_read,
+	.close = php_rust_stdio_close,
+	.flush = php_rust_stdio_flush,
+	.label = "STDIO",
+};
+
+static php_stream *php_rust_stdio_open(int fd)
+{
+	return php_stream_alloc(&php_rust_stdio_ops, (void *) (uintptr_t) fd, NULL, fd == 0 ? "rb" : "wb");
+}
+
+/* Open php://stdin, php://stdout and php://stderr on the Rust streams, and
+ * everything else with the standard php:// wrapper. */
+static php_stream *php_rust_php_wrapper_opener(php_stream_wrapper *wrapper, const char *path, const char *mode,
+	int options, zend_string **opened_path, php_stream_context *context STREAMS_DC)
+{
+	if (!strncasecmp(path, "php://", 6)) {
+		const char *name = path + 6;
+
+		if (!strcasecmp(name, "stdin")) {
+			return php_rust_stdio_open(0);
+		} else if (!strcasecmp(name, "stdout")) {
+			return php_rust_stdio_open(1);
+		} else if (!strcasecmp(name, "stderr")) {
+			return php_rust_stdio_open(2);
+		}
+	}
+
+	return php_stream_php_wrapper.wops->stream_opener(wrapper, path, mode, options, opened_path, context STREAMS_REL_CC);
+}
+
+static const php_stream_wrapper_ops php_rust_php_wrapper_ops = {
+	.stream_opener = php_rust_php_wrapper_opener,
+	.label = "PHP",
+};
+
+static const php_stream_wrapper php_rust_php_wrapper = {
+	&php_rust_php_wrapper_ops,
+	NULL,
+	0,
+};
+
+static void php_rust_register_stdio_constant(const char *name, php_stream *stream)
+{
+	zend_constant constant;
+
+	/* Like php-cli, keep the streams usable after a script fclose()s them, for
+	 * anything that writes to them later in the request. */
+	stream->flags |= PHP_STREAM_FLAG_NO_CLOSE;
+	php_stream_to_zval(stream, &constant.value);
+	ZEND_CONSTANT_SET_FLAGS(&constant, 0, 0);
+	constant.name = zend_string_init_interned(name, strlen(name), 0);
+	zend_register_constant(&constant);
+}
+
+/* Set up a started request like the CLI SAPI does: no headers, the STDIN,
+ * STDOUT and STDERR constants, and php://stdin and friends, all reading from
+ * and writing to `handlers`. */
+void php_rust_cli_request_startup(struct php_rust_stdio handlers)
+{
+	zend_string *protocol;
+
+	stdio = handlers;
+
+	SG(headers_sent) = 1;
+	SG(request_info).no_headers = 1;
+
+	php_rust_register_stdio_constant("STDIN", php_rust_stdio_open(0));
+	php_rust_register_stdio_constant("STDOUT", php_rust_stdio_open(1));
+	php_rust_register_stdio_constant("STDERR", php_rust_stdio_open(2));
+
+	protocol = zend_string_init("php", sizeof("php") - 1, 0);
+	php_register_url_stream_wrapper_volatile(protocol, (php_stream_wrapper *) &php_rust_php_wrapper);
+	zend_string_release(protocol);
+}
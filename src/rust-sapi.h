@@ -40,7 +40,7 @@ struct partial_sapi_module_struct {
 	char *(*getenv)(const char *name, size_t name_len);
 
 	//int (*header_handler)(sapi_header_struct *sapi_header, sapi_header_op_enum op, sapi_headers_struct *sapi_headers);
-	//int (*send_headers)(sapi_headers_struct *sapi_headers);
+	int (*send_headers)(sapi_headers_struct *sapi_headers);
 	void (*send_header)(sapi_header_struct *sapi_header, void *server_context);
 
 	size_t (*read_post)(char *buffer, size_t count_bytes);
@@ -52,10 +52,67 @@ struct partial_sapi_module_struct {
 	void (*terminate_process)(void);
 };
 
-int php_rust_init(struct partial_sapi_module_struct module, void *server_context, char* executable_location);
+/* What to do with an error once the error hook has seen it. */
+enum php_rust_error_action {
+	/* Let PHP display and log the error as usual. */
+	PHP_RUST_ERROR_PASS,
+	/* PHP still records the error (error_get_last(), exit status, bailing out
+	 * on fatal errors), but does not display or log it. */
+	PHP_RUST_ERROR_HANDLED,
+	/* Like PHP_RUST_ERROR_HANDLED, but also abort the code as if the error was
+	 * fatal, if it runs inside zend_try. */
+	PHP_RUST_ERROR_ESCALATE,
+};
+
+/* Receives every error PHP raises on the current thread. */
+struct php_rust_error_hook {
+	enum php_rust_error_action (*callback)(void *data, int type, const char *file, size_t file_len, uint32_t lineno, const char *message, size_t message_len);
+	void *data;
+};
+
+/* The HTTP request the next request is started for, see
+ * php_rust_set_request_info. Strings that are NULL are not set. */
+struct php_rust_request_info {
+	const char *method;
+	const char *uri;
+	const char *query_string;
+	const char *content_type;
+	int64_t content_length;
+	const char *path_translated;
+	/* The Authorization header, for $_SERVER['PHP_AUTH_USER'] and friends. */
+	const char *authorization;
+};
+
+/* How PHP reads its configuration at startup. `defaults` holds NUL-terminated
+ * name/value pairs, ending with an empty name. `entries` holds settings in INI
+ * syntax that override INI files, like php-cli's -d. */
+struct php_rust_ini {
+	const char *path_override;
+	const char *defaults;
+	const char *entries;
+	bool ignore;
+};
+
+/* The standard streams of the CLI SAPI, which are implemented in Rust.
+ * `fd` is 0 for stdin, 1 for stdout and 2 for stderr. */
+struct php_rust_stdio {
+	ssize_t (*read)(int fd, char *buf, size_t count);
+	ssize_t (*write)(int fd, const char *buf, size_t count);
+	int (*flush)(int fd);
+};
+
+int php_rust_init(struct partial_sapi_module_struct module, void *server_context, char* executable_location, const struct php_rust_ini *ini);
 void php_rust_clear_server_context();
 void php_rust_set_tmp_server_ctx(void *server_context);
 void php_rust_set_server_context();
+void *php_rust_get_server_context();
+void php_rust_thread_attach();
+void php_rust_thread_detach();
 sapi_request_info* php_rust_get_request_info();
+void php_rust_set_argv(int argc, char **argv);
+const char *php_rust_script_path();
+void php_rust_set_request_info(const struct php_rust_request_info *info);
+void php_rust_cli_request_startup(struct php_rust_stdio handlers);
+struct php_rust_error_hook php_rust_set_error_hook(struct php_rust_error_hook hook);
 
 #endif /* _PHP_RUST_H_ */
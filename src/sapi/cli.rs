@@ -0,0 +1,198 @@
+use std::{
+    borrow::Cow,
+    ffi::{c_char, c_int, CStr},
+    io::{self, Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::safe::{server_context, Sapi, TrackVarsArray};
+use crate::sys::{
+    php_module_shutdown, php_module_startup, php_rust_cli_request_startup, php_rust_script_path,
+    php_rust_stdio, ssize_t,
+};
+
+/// A SAPI that behaves like `php-cli`, so command line tools like Composer, PHPUnit or
+/// artisan can run in-process.
+///
+/// `PHP_SAPI` is `cli`, no headers are sent, the `STDIN`, `STDOUT` and `STDERR` constants
+/// are defined, and `$_SERVER` contains the environment, with `SCRIPT_FILENAME` and friends
+/// set to the first argument. The standard streams, including `php://stdin` and friends,
+/// read from and write to the [`CliStdio`] of the request.
+/// Pass the arguments with [`Context::argv`](crate::exec::Context::argv), and get the exit
+/// code with [`Context::exit_status`](crate::exec::Context::exit_status).
+pub struct CliSapi;
+
+/// The standard streams of a request run by [`CliSapi`].
+pub struct CliStdio {
+    pub stdin: Box<dyn Read + Send>,
+    pub stdout: Box<dyn Write + Send>,
+    pub stderr: Box<dyn Write + Send>,
+}
+
+impl CliStdio {
+    pub fn new(
+        stdin: impl Read + Send + 'static,
+        stdout: impl Write + Send + 'static,
+        stderr: impl Write + Send + 'static,
+    ) -> Self {
+        Self {
+            stdin: Box::new(stdin),
+            stdout: Box::new(stdout),
+            stderr: Box::new(stderr),
+        }
+    }
+}
+
+impl Default for CliStdio {
+    /// Use the standard streams of the process.
+    fn default() -> Self {
+        Self::new(io::stdin(), io::stdout(), io::stderr())
+    }
+}
+
+fn stdio_result(result: io::Result<usize>) -> ssize_t {
+    result.map_or(-1, |len| len as ssize_t)
+}
+
+unsafe extern "C" fn stdio_read(fd: c_int, buf: *mut c_char, count: usize) -> ssize_t {
+    let Some(stdio) = server_context::<CliSapi>() else {
+        return -1;
+    };
+    let buf = std::slice::from_raw_parts_mut(buf as *mut u8, count);
+
+    match fd {
+        0 => stdio_result(stdio.stdin.read(buf)),
+        _ => -1,
+    }
+}
+
+unsafe extern "C" fn stdio_write(fd: c_int, buf: *const c_char, count: usize) -> ssize_t {
+    let Some(stdio) = server_context::<CliSapi>() else {
+        return -1;
+    };
+    let buf = std::slice::from_raw_parts(buf as *const u8, count);
+
+    let result = match fd {
+        1 => stdio.stdout.write_all(buf),
+        2 => stdio.stderr.write_all(buf),
+        _ => return -1,
+    };
+    stdio_result(result.map(|_| count))
+}
+
+unsafe extern "C" fn stdio_flush(fd: c_int) -> c_int {
+    let Some(stdio) = server_context::<CliSapi>() else {
+        return -1;
+    };
+
+    let result = match fd {
+        1 => stdio.stdout.flush(),
+        2 => stdio.stderr.flush(),
+        _ => Ok(()),
+    };
+    result.map_or(-1, |_| 0)
+}
+
+impl Sapi for CliSapi {
+    type Context = CliStdio;
+
+    const name: *const c_char = c"cli".as_ptr();
+
+    const pretty_name: *const c_char = c"Command Line Interface".as_ptr();
+
+    fn startup(module: *mut crate::sys::sapi_module_struct) -> i32 {
+        unsafe { php_module_startup(module, std::ptr::null_mut()) }
+    }
+
+    fn shutdown() -> i32 {
+        unsafe {
+            php_module_shutdown();
+        }
+        0
+    }
+
+    fn activate() -> i32 {
+        0
+    }
+
+    fn deactivate() -> i32 {
+        0
+    }
+
+    fn ub_write(ctx: &mut Self::Context, bytes: &[u8]) -> usize {
+        match ctx.stdout.write_all(bytes) {
+            Ok(()) => bytes.len(),
+            Err(_) => 0,
+        }
+    }
+
+    fn flush(ctx: &mut Self::Context) {
+        let _ = ctx.stdout.flush();
+    }
+
+    fn get_stat() -> *mut crate::sys::zend_stat_t {
+        std::ptr::null_mut()
+    }
+
+    fn getenv(_ctx: &mut Self::Context, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn send_header(_ctx: &mut Self::Context, _header: String) {
+        // The CLI has no headers.
+    }
+
+    fn read_post(_ctx: &mut Self::Context, _buffer: &mut [u8]) -> usize {
+        0
+    }
+
+    fn read_cookies(_ctx: &mut Self::Context) -> String {
+        "".to_string()
+    }
+
+    fn register_server_variables(_ctx: &mut Self::Context, track_vars_array: &mut TrackVarsArray) {
+        // `vars` panics on names or values that aren't UTF-8, and this is called from C.
+        for (key, value) in std::env::vars_os() {
+            track_vars_array.insert(&key.to_string_lossy(), &value.to_string_lossy());
+        }
+
+        // Like php-cli, which sets these to the script it runs.
+        let script = unsafe { php_rust_script_path() };
+        let script = if script.is_null() {
+            Cow::Borrowed("")
+        } else {
+            unsafe { CStr::from_ptr(script) }.to_string_lossy()
+        };
+        for key in [
+            "PHP_SELF",
+            "SCRIPT_NAME",
+            "SCRIPT_FILENAME",
+            "PATH_TRANSLATED",
+        ] {
+            track_vars_array.insert(key, &script);
+        }
+        track_vars_array.insert("DOCUMENT_ROOT", "");
+    }
+
+    fn get_request_time(_ctx: &mut Self::Context) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64())
+    }
+
+    fn terminate_process() {}
+
+    fn log_message(ctx: &mut Self::Context, message: &str, _syslog_type_int: i32) {
+        let _ = writeln!(ctx.stderr, "{}", message);
+    }
+
+    fn on_request_start(_ctx: &mut Self::Context) {
+        unsafe {
+            php_rust_cli_request_startup(php_rust_stdio {
+                read: Some(stdio_read),
+                write: Some(stdio_write),
+                flush: Some(stdio_flush),
+            });
+        }
+    }
+}
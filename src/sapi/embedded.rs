@@ -1,8 +1,12 @@
-use std::io::Write;
+use std::{
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use super::safe::{Sapi, TrackVarsArray};
-use crate::sys::{
-    php_module_shutdown, php_module_startup, php_request_shutdown, sapi_shutdown,
+use crate::{
+    exec::current_request_id,
+    sys::{php_module_shutdown, php_module_startup},
 };
 
 
@@ -21,17 +25,8 @@ impl Sapi for EmbeddedSapi {
 
     fn shutdown() -> i32 {
         unsafe {
-            /* Request shutdown (RSHUTDOWN) */
-            php_request_shutdown(std::ptr::null_mut());
-
             /* Module shutdown (MSHUTDOWN) */
             php_module_shutdown();
-
-            /* SAPI shutdown (SSHUTDOWN) */
-            sapi_shutdown();
-            
-            #[cfg(feature = "zts")]
-            crate::sys::tsrm_shutdown();
         }
         0
     }
@@ -44,8 +39,11 @@ impl Sapi for EmbeddedSapi {
         0
     }
 
-    fn ub_write(str: &str) -> usize {
-        std::io::stdout().write(str.as_bytes()).unwrap()
+    fn ub_write(_ctx: &mut Self::Context, bytes: &[u8]) -> usize {
+        match std::io::stdout().write_all(bytes) {
+            Ok(()) => bytes.len(),
+            Err(_) => 0,
+        }
     }
 
     fn flush(_ctx: &mut Self::Context) {
@@ -57,9 +55,8 @@ impl Sapi for EmbeddedSapi {
         todo!()
     }
 
-    fn getenv(name: &str) -> &Option<String> {
-        // TODO: Don't leak memory
-        Box::leak(Box::new(std::env::var(name).ok()))
+    fn getenv(_ctx: &mut Self::Context, name: &str) -> Option<String> {
+        std::env::var(name).ok()
     }
 
     /*fn header_handler(
@@ -76,32 +73,47 @@ impl Sapi for EmbeddedSapi {
         0
     }*/
 
-    fn send_header(_header: String, _ctx: Option<&mut Self::Context>) {
+    fn send_header(_ctx: &mut Self::Context, _header: String) {
         //println!("send_header: {:?}", header);
     }
 
-    fn read_post(_buffer: &mut [u8]) -> usize {
+    fn read_post(_ctx: &mut Self::Context, _buffer: &mut [u8]) -> usize {
         0
     }
 
-    fn read_cookies() -> String {
+    fn read_cookies(_ctx: &mut Self::Context) -> String {
         "".to_string()
     }
 
-    fn register_server_variables(_track_vars_array: &mut TrackVarsArray) {
+    fn register_server_variables(_ctx: &mut Self::Context, _track_vars_array: &mut TrackVarsArray) {
         // Empty
     }
 
-    fn get_request_time() -> f64 {
-        tracing::debug!("get_request_time");
-        0.0
+    fn get_request_time(_ctx: &mut Self::Context) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64())
     }
 
     fn terminate_process() {
-        todo!()
+        // The host process decides when to exit. The request itself is already aborted.
+        tracing::debug!("terminate_process");
     }
 
-    fn log_message(_message: &str, _syslog_type_int: i32) {
-        todo!()
+    fn log_message(_ctx: &mut Self::Context, message: &str, syslog_type_int: i32) {
+        log_to_tracing(message, syslog_type_int);
+    }
+}
+
+/// Emit a message PHP logs as a `tracing` event.
+pub(crate) fn log_to_tracing(message: &str, syslog_type_int: i32) {
+    let request_id = current_request_id();
+
+    // PHP passes syslog priorities, from LOG_EMERG (0) to LOG_DEBUG (7).
+    match syslog_type_int {
+        ..=3 => tracing::error!(request_id, "{}", message),
+        4 => tracing::warn!(request_id, "{}", message),
+        5 | 6 => tracing::info!(request_id, "{}", message),
+        _ => tracing::debug!(request_id, "{}", message),
     }
 }
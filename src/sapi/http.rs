@@ -0,0 +1,472 @@
+use std::{
+    ffi::{c_char, CString},
+    net::SocketAddr,
+    ptr::null,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    embedded::log_to_tracing,
+    safe::{Headers, Sapi, TrackVarsArray},
+};
+use crate::{
+    error::PhpError,
+    exec::Context,
+    sys::{
+        php_module_shutdown, php_module_startup, php_rust_request_info, php_rust_set_request_info,
+    },
+};
+
+/// A SAPI that runs PHP for an HTTP request, like a web server module or FastCGI does.
+///
+/// The [`HttpRequest`] of the [`HttpExchange`] is used for `$_GET`, `$_POST`, `$_COOKIE`,
+/// `$_FILES`, `$_SERVER` and `php://input`, and the status, headers and output of the
+/// request are collected in its [`HttpResponse`]. Use [`Context::handle_request`] to run a
+/// request and get its response.
+pub struct HttpSapi;
+
+/// An HTTP request for PHP to handle with [`HttpSapi`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    /// The request target: the path, and the query string if there is one.
+    pub uri: String,
+    /// The protocol, such as `HTTP/1.1`.
+    pub protocol: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// The directory the site is served from, as `DOCUMENT_ROOT`.
+    pub document_root: String,
+    /// The path of the script that handles the request, as `SCRIPT_FILENAME`.
+    pub script_filename: String,
+    /// The URL path of the script, as `SCRIPT_NAME`.
+    pub script_name: String,
+    /// The part of the path after the script name, as `PATH_INFO`.
+    pub path_info: Option<String>,
+    pub remote_addr: Option<SocketAddr>,
+    pub server_addr: Option<SocketAddr>,
+    /// Whether the request was received over TLS, which sets `HTTPS`.
+    pub https: bool,
+}
+
+impl HttpRequest {
+    /// Create a request without headers or body, for the script at its path.
+    pub fn new(method: impl Into<String>, uri: impl Into<String>) -> Self {
+        let uri = uri.into();
+        let path = uri.split_once('?').map_or(uri.as_str(), |(path, _)| path);
+
+        Self {
+            method: method.into(),
+            script_name: path.to_string(),
+            uri,
+            protocol: "HTTP/1.1".to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            document_root: String::new(),
+            script_filename: String::new(),
+            path_info: None,
+            remote_addr: None,
+            server_addr: None,
+            https: false,
+        }
+    }
+
+    /// Add a header.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Handle the request with the script at `script_name` in `document_root`, like a
+    /// front controller.
+    pub fn script(
+        mut self,
+        document_root: impl Into<String>,
+        script_name: impl Into<String>,
+    ) -> Self {
+        self.document_root = document_root.into();
+        self.script_name = script_name.into();
+        self.script_filename = format!(
+            "{}/{}",
+            self.document_root.trim_end_matches('/'),
+            self.script_name.trim_start_matches('/')
+        );
+        self
+    }
+
+    /// Get the path of the request, without the query string.
+    pub fn path(&self) -> &str {
+        self.uri
+            .split_once('?')
+            .map_or(self.uri.as_str(), |(path, _)| path)
+    }
+
+    /// Get the query string of the request, which is empty if there is none.
+    pub fn query_string(&self) -> &str {
+        self.uri.split_once('?').map_or("", |(_, query)| query)
+    }
+
+    /// Get the values of a header, which may be given several times.
+    pub fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get the first value of a header.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn content_length(&self) -> Option<i64> {
+        match self.header_value("Content-Length") {
+            Some(length) => length.trim().parse().ok(),
+            None => (!self.body.is_empty()).then_some(self.body.len() as i64),
+        }
+    }
+
+    fn server_name(&self) -> String {
+        match self.header_value("Host") {
+            // Strip the port, keeping IPv6 addresses in brackets intact.
+            Some(host) => match host.rsplit_once(':') {
+                Some((name, port)) if !port.contains(']') => name.to_string(),
+                _ => host.to_string(),
+            },
+            None => self
+                .server_addr
+                .map_or_else(|| "localhost".to_string(), |addr| addr.ip().to_string()),
+        }
+    }
+}
+
+/// The response PHP produced for an [`HttpRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// The headers in the order PHP sends them, including `Set-Cookie` headers and the
+    /// default `Content-Type`.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Default for HttpResponse {
+    fn default() -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+impl HttpResponse {
+    /// Get the first value of a header.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Receives the response of an [`HttpExchange`] while PHP produces it, instead of it being
+/// collected in [`HttpExchange::response`].
+pub trait ResponseSink: Send {
+    /// Called once per request with the status and headers, before any part of the body.
+    fn send_head(&mut self, response: &HttpResponse);
+    /// Called with each part of the body as PHP writes it.
+    fn write_body(&mut self, bytes: &[u8]);
+}
+
+/// The strings PHP's request info points to while a request runs.
+#[derive(Default)]
+struct RequestInfo {
+    method: CString,
+    uri: CString,
+    query_string: CString,
+    content_type: Option<CString>,
+    path_translated: CString,
+    authorization: Option<CString>,
+}
+
+fn c_string(value: &str) -> CString {
+    CString::new(value).unwrap_or_default()
+}
+
+fn as_ptr(value: &Option<CString>) -> *const c_char {
+    value.as_ref().map_or(null(), |value| value.as_ptr())
+}
+
+/// A request run by [`HttpSapi`], and the response it produced.
+pub struct HttpExchange {
+    pub request: HttpRequest,
+    /// The response of the request, which is reset when the next request starts.
+    ///
+    /// PHP sends the headers before the first output, and output may be buffered, so the
+    /// response is only complete once the request is closed.
+    pub response: HttpResponse,
+    sink: Option<Box<dyn ResponseSink>>,
+    body_read: usize,
+    info: RequestInfo,
+}
+
+impl HttpExchange {
+    pub fn new(request: HttpRequest) -> Self {
+        Self {
+            request,
+            response: HttpResponse::default(),
+            sink: None,
+            body_read: 0,
+            info: RequestInfo::default(),
+        }
+    }
+
+    /// Stream the response to `sink`, which is dropped with the exchange. The status and
+    /// headers are still kept in [`response`](Self::response), but the body is not.
+    pub fn with_sink(request: HttpRequest, sink: impl ResponseSink + 'static) -> Self {
+        Self {
+            sink: Some(Box::new(sink)),
+            ..Self::new(request)
+        }
+    }
+}
+
+impl Sapi for HttpSapi {
+    type Context = HttpExchange;
+
+    const name: *const c_char = c"rust-http".as_ptr();
+
+    const pretty_name: *const c_char = c"Rust HTTP".as_ptr();
+
+    fn startup(module: *mut crate::sys::sapi_module_struct) -> i32 {
+        unsafe { php_module_startup(module, std::ptr::null_mut()) }
+    }
+
+    fn shutdown() -> i32 {
+        unsafe {
+            php_module_shutdown();
+        }
+        0
+    }
+
+    fn activate() -> i32 {
+        0
+    }
+
+    fn deactivate() -> i32 {
+        0
+    }
+
+    fn ub_write(ctx: &mut Self::Context, bytes: &[u8]) -> usize {
+        match &mut ctx.sink {
+            Some(sink) => sink.write_body(bytes),
+            None => ctx.response.body.extend_from_slice(bytes),
+        }
+        bytes.len()
+    }
+
+    fn flush(_ctx: &mut Self::Context) {}
+
+    fn get_stat() -> *mut crate::sys::zend_stat_t {
+        std::ptr::null_mut()
+    }
+
+    fn getenv(_ctx: &mut Self::Context, _name: &str) -> Option<String> {
+        // PHP falls back to the environment of the process.
+        None
+    }
+
+    fn send_headers(ctx: &mut Self::Context, headers: Headers) -> bool {
+        if let Ok(status) = u16::try_from(headers.http_response_code) {
+            if status > 0 {
+                ctx.response.status = status;
+            }
+        }
+
+        ctx.response.headers = headers
+            .headers
+            .iter()
+            .filter_map(|header| header.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        if let Some(sink) = &mut ctx.sink {
+            sink.send_head(&ctx.response);
+        }
+
+        true
+    }
+
+    fn send_header(_ctx: &mut Self::Context, _header: String) {}
+
+    fn read_post(ctx: &mut Self::Context, buffer: &mut [u8]) -> usize {
+        let body = &ctx.request.body[ctx.body_read..];
+        let len = body.len().min(buffer.len());
+
+        buffer[..len].copy_from_slice(&body[..len]);
+        ctx.body_read += len;
+        len
+    }
+
+    fn read_cookies(ctx: &mut Self::Context) -> String {
+        ctx.request
+            .header_values("Cookie")
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    fn register_server_variables(ctx: &mut Self::Context, track_vars_array: &mut TrackVarsArray) {
+        let request = &ctx.request;
+
+        track_vars_array.insert("SERVER_SOFTWARE", "libphp");
+        track_vars_array.insert("SERVER_PROTOCOL", &request.protocol);
+        track_vars_array.insert("GATEWAY_INTERFACE", "CGI/1.1");
+        track_vars_array.insert("REQUEST_METHOD", &request.method);
+        track_vars_array.insert("REQUEST_URI", &request.uri);
+        track_vars_array.insert("QUERY_STRING", request.query_string());
+        track_vars_array.insert("DOCUMENT_ROOT", &request.document_root);
+        track_vars_array.insert("SCRIPT_FILENAME", &request.script_filename);
+        track_vars_array.insert("SCRIPT_NAME", &request.script_name);
+
+        let mut php_self = request.script_name.clone();
+
+        if let Some(path_info) = &request.path_info {
+            track_vars_array.insert("PATH_INFO", path_info);
+            php_self.push_str(path_info);
+        }
+        track_vars_array.insert("PHP_SELF", &php_self);
+
+        track_vars_array.insert("SERVER_NAME", &request.server_name());
+        track_vars_array.insert(
+            "REQUEST_SCHEME",
+            if request.https { "https" } else { "http" },
+        );
+
+        if request.https {
+            track_vars_array.insert("HTTPS", "on");
+        }
+
+        if let Some(addr) = request.server_addr {
+            track_vars_array.insert("SERVER_ADDR", &addr.ip().to_string());
+            track_vars_array.insert("SERVER_PORT", &addr.port().to_string());
+        }
+
+        if let Some(addr) = request.remote_addr {
+            track_vars_array.insert("REMOTE_ADDR", &addr.ip().to_string());
+            track_vars_array.insert("REMOTE_PORT", &addr.port().to_string());
+        }
+
+        if let Some(content_type) = request.header_value("Content-Type") {
+            track_vars_array.insert("CONTENT_TYPE", content_type);
+        }
+
+        if let Some(length) = request.content_length() {
+            track_vars_array.insert("CONTENT_LENGTH", &length.to_string());
+        }
+
+        // Like CGI, other headers become HTTP_* variables, with repeated headers joined.
+        let mut names: Vec<String> = Vec::new();
+
+        for (name, _) in &request.headers {
+            // HTTP_PROXY would be mistaken for the proxy setting of the environment
+            // (httpoxy), so the Proxy header is dropped like most servers do.
+            if name.eq_ignore_ascii_case("Content-Type")
+                || name.eq_ignore_ascii_case("Content-Length")
+                || name.eq_ignore_ascii_case("Proxy")
+                || names.iter().any(|seen| seen.eq_ignore_ascii_case(name))
+            {
+                continue;
+            }
+            names.push(name.clone());
+
+            let separator = if name.eq_ignore_ascii_case("Cookie") {
+                "; "
+            } else {
+                ", "
+            };
+            let value = request
+                .header_values(name)
+                .collect::<Vec<_>>()
+                .join(separator);
+            let key = format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_"));
+
+            track_vars_array.insert(&key, &value);
+        }
+    }
+
+    fn get_request_time(_ctx: &mut Self::Context) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64())
+    }
+
+    fn terminate_process() {
+        tracing::debug!("terminate_process");
+    }
+
+    fn log_message(_ctx: &mut Self::Context, message: &str, syslog_type_int: i32) {
+        log_to_tracing(message, syslog_type_int);
+    }
+
+    fn on_before_request_init(ctx: &mut Self::Context) {
+        let request = &ctx.request;
+
+        ctx.response = HttpResponse::default();
+        ctx.body_read = 0;
+        ctx.info = RequestInfo {
+            method: c_string(&request.method),
+            uri: c_string(&request.uri),
+            query_string: c_string(request.query_string()),
+            content_type: request.header_value("Content-Type").map(c_string),
+            path_translated: c_string(&request.script_filename),
+            authorization: request.header_value("Authorization").map(c_string),
+        };
+
+        unsafe {
+            php_rust_set_request_info(&php_rust_request_info {
+                method: ctx.info.method.as_ptr(),
+                uri: ctx.info.uri.as_ptr(),
+                query_string: ctx.info.query_string.as_ptr(),
+                content_type: as_ptr(&ctx.info.content_type),
+                content_length: request.content_length().unwrap_or(0),
+                path_translated: ctx.info.path_translated.as_ptr(),
+                authorization: as_ptr(&ctx.info.authorization),
+            });
+        }
+    }
+}
+
+impl Context<'_, HttpSapi> {
+    /// Run the script of `request` in a new request, and get the response it produced.
+    ///
+    /// The script may end the response with `exit()`, as it would in a web server. Any
+    /// other [`PhpError`] is returned instead of the response.
+    pub fn handle_request(&mut self, request: HttpRequest) -> Result<HttpResponse, PhpError> {
+        self.close();
+
+        let script = request.script_filename.clone();
+        self.sapi_context().request = request;
+
+        let result = self.execute_file(&script, false).map(drop);
+
+        // Shutting the request down sends the headers and flushes the output buffers.
+        self.close();
+
+        let response = std::mem::take(&mut self.sapi_context().response);
+
+        match result {
+            Ok(()) | Err(PhpError::Exit(_)) => Ok(response),
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -1,3 +1,5 @@
 pub mod raw;
 pub mod safe;
 pub mod embedded;
+pub mod cli;
+pub mod http;
@@ -1,8 +1,8 @@
 use std::ffi::{c_char, c_double, c_int, c_void};
 
 use crate::sys::{
-    partial_sapi_module_struct, sapi_header_struct, sapi_module_struct, zend_result, zend_stat_t,
-    zval,
+    partial_sapi_module_struct, sapi_header_struct, sapi_headers_struct, sapi_module_struct,
+    zend_result, zend_stat_t, zval,
 };
 
 pub unsafe trait RawPhpSapi {
@@ -23,8 +23,10 @@ pub unsafe trait RawPhpSapi {
     unsafe extern "C" fn get_stat() -> *mut zend_stat_t;
     unsafe extern "C" fn getenv(name: *const c_char, name_len: usize) -> *mut c_char;
 
-    // TODO: Error handler would require variadic functions, which are currently only supported in nightly Rust
+    // Errors are routed through the error hook in rust-sapi.c instead of sapi_error, which is
+    // variadic. See Context::set_error_handler.
 
+    unsafe extern "C" fn send_headers(sapi_headers: *mut sapi_headers_struct) -> c_int;
     unsafe extern "C" fn send_header(
         sapi_header: *mut sapi_header_struct,
         _server_context: *mut c_void,
@@ -39,7 +41,8 @@ pub unsafe trait RawPhpSapi {
 
     unsafe extern "C" fn log_message(message: *const c_char, syslog_type_int: c_int) -> ();
     
-    fn on_before_request_init() {}
+    fn on_before_request_init(_ctx: &mut Self::Context) {}
+    fn on_request_start(_ctx: &mut Self::Context) {}
 }
 
 pub fn get_partial_module_for_c<Sapi: RawPhpSapi>() -> partial_sapi_module_struct {
@@ -54,6 +57,7 @@ pub fn get_partial_module_for_c<Sapi: RawPhpSapi>() -> partial_sapi_module_struc
         flush: Some(Sapi::flush),
         get_stat: Some(Sapi::get_stat),
         getenv: Some(Sapi::getenv),
+        send_headers: Some(Sapi::send_headers),
         send_header: Some(Sapi::send_header),
         read_post: Some(Sapi::read_post),
         read_cookies: Some(Sapi::read_cookies),
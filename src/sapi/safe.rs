@@ -1,14 +1,21 @@
-use std::{ffi::CStr, ptr::NonNull};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    io::Write,
+    ptr::NonNull,
+};
 
 use crate::{
     sys::{
         libphp_zval_addref_p, php_register_variable_ex, php_register_variable_safe,
-        php_rust_set_server_context, sapi_module_struct,
+        php_rust_get_server_context, php_rust_set_server_context, sapi_header_struct,
+        sapi_headers_struct, sapi_module_struct, zend_llist_get_first_ex, zend_llist_get_next_ex,
+        zend_llist_position, SAPI_HEADER_DO_SEND, SAPI_HEADER_SENT_SUCCESSFULLY,
     },
     value::Value,
 };
 
-use super::raw::RawPhpSapi;
+use super::{embedded::log_to_tracing, raw::RawPhpSapi};
 
 #[derive(Debug, Clone, Copy)]
 pub enum SapiHeaderOp {
@@ -96,18 +103,51 @@ pub trait Sapi {
     fn shutdown() -> i32;
     fn activate() -> i32;
     fn deactivate() -> i32;
-    fn ub_write(str: &str) -> usize;
+    /// Write a chunk of output for the request owning `ctx`.
+    ///
+    /// The bytes are passed on as PHP produced them and are not necessarily valid UTF-8.
+    fn ub_write(ctx: &mut Self::Context, bytes: &[u8]) -> usize;
     fn flush(ctx: &mut Self::Context);
     fn get_stat() -> *mut crate::sys::zend_stat_t;
-    fn getenv(name: &str) -> &Option<String>;
-    fn send_header(header: String, ctx: Option<&mut Self::Context>);
-    fn read_post(buffer: &mut [u8]) -> usize;
-    fn read_cookies() -> String;
-    fn register_server_variables(track_vars_array: &mut TrackVarsArray);
-    fn get_request_time() -> f64;
+    fn getenv(ctx: &mut Self::Context, name: &str) -> Option<String>;
+    /// Send the status and headers of the response, which happens before its first output.
+    ///
+    /// Return false to have PHP pass the status line and headers to
+    /// [`send_header`](Self::send_header) one at a time instead.
+    fn send_headers(_ctx: &mut Self::Context, _headers: Headers) -> bool {
+        false
+    }
+    fn send_header(ctx: &mut Self::Context, header: String);
+    fn read_post(ctx: &mut Self::Context, buffer: &mut [u8]) -> usize;
+    fn read_cookies(ctx: &mut Self::Context) -> String;
+    fn register_server_variables(ctx: &mut Self::Context, track_vars_array: &mut TrackVarsArray);
+    fn get_request_time(ctx: &mut Self::Context) -> f64;
+    /// Called when PHP wants the process to end, for example after a hard timeout.
+    ///
+    /// This is called from C, where a panic aborts the host process.
     fn terminate_process();
-    fn log_message(message: &str, syslog_type_int: i32);
-    fn on_before_request_init() {}
+    fn log_message(ctx: &mut Self::Context, message: &str, syslog_type_int: i32);
+    fn on_before_request_init(_ctx: &mut Self::Context) {}
+    /// Called once a request has started, before any code runs in it.
+    fn on_request_start(_ctx: &mut Self::Context) {}
+}
+
+thread_local! {
+    // PHP copies the value returned by getenv, so it only has to outlive the callback.
+    static GETENV_VALUE: RefCell<Option<CString>> = const { RefCell::new(None) };
+    // PHP keeps the cookies of a request until the server context is cleared, before the
+    // next request reads them.
+    static COOKIE_DATA: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Get the context of the request currently running on this thread.
+///
+/// # Safety
+///
+/// The server context must have been set by a `Context` using the SAPI `T`.
+pub(crate) unsafe fn server_context<'a, T: Sapi>() -> Option<&'a mut T::Context> {
+    let ctx = php_rust_get_server_context() as *mut T::Context;
+    NonNull::new(ctx).map(|mut ctx| ctx.as_mut())
 }
 
 unsafe impl<T: Sapi> RawPhpSapi for T {
@@ -135,13 +175,16 @@ unsafe impl<T: Sapi> RawPhpSapi for T {
     }
 
     unsafe extern "C" fn ub_write(str: *const std::ffi::c_char, size: usize) -> usize {
-        //dbg!(str, size);
         let slice = unsafe { std::slice::from_raw_parts(str as *const u8, size) };
-        if let Ok(string) = std::str::from_utf8(slice) {
-            T::ub_write(string)
+        if let Some(ctx) = server_context::<T>() {
+            T::ub_write(ctx, slice)
         } else {
-            println!("Failed to convert to utf8");
-            size
+            // Output from outside a request, like startup errors, goes where the process
+            // writes its own.
+            match std::io::stdout().write_all(slice) {
+                Ok(()) => size,
+                Err(_) => 0,
+            }
         }
     }
 
@@ -164,20 +207,21 @@ unsafe impl<T: Sapi> RawPhpSapi for T {
         name_len: usize,
     ) -> *mut std::ffi::c_char {
         let slice = unsafe { std::slice::from_raw_parts(name as *const u8, name_len) };
-        let string = std::str::from_utf8(slice).unwrap();
-        if let Some(var) = T::getenv(string) {
-            let var_mem = unsafe {
-                std::alloc::alloc(
-                    std::alloc::Layout::from_size_align(var.len() + 1, std::mem::align_of::<u8>())
-                        .expect("Failed to create layout"),
-                )
-            };
-            std::ptr::copy(var.as_ptr(), var_mem, var.len());
-            std::ptr::write(var_mem.add(var.len()), 0);
-            var_mem as *mut std::ffi::c_char
-        } else {
-            std::ptr::null_mut()
-        }
+        // Names that aren't UTF-8 can't be looked up, so PHP sees them as unset.
+        let Ok(string) = std::str::from_utf8(slice) else {
+            return std::ptr::null_mut();
+        };
+        let Some(ctx) = server_context::<T>() else {
+            tracing::debug!("server_context is null");
+            return std::ptr::null_mut();
+        };
+        let var = T::getenv(ctx, string).and_then(|var| CString::new(var).ok());
+        GETENV_VALUE.with_borrow_mut(|value| {
+            *value = var;
+            value.as_ref().map_or(std::ptr::null_mut(), |var| {
+                var.as_ptr() as *mut std::ffi::c_char
+            })
+        })
     }
 
     /*unsafe extern "C" fn header_handler(
@@ -211,6 +255,19 @@ unsafe impl<T: Sapi> RawPhpSapi for T {
         T::send_headers(headers)
     }*/
 
+    unsafe extern "C" fn send_headers(sapi_headers: *mut sapi_headers_struct) -> std::ffi::c_int {
+        let Some(ctx) = server_context::<T>() else {
+            tracing::debug!("server_context is null");
+            return SAPI_HEADER_DO_SEND as std::ffi::c_int;
+        };
+
+        if T::send_headers(ctx, load_sapi_headers(sapi_headers)) {
+            SAPI_HEADER_SENT_SUCCESSFULLY as std::ffi::c_int
+        } else {
+            SAPI_HEADER_DO_SEND as std::ffi::c_int
+        }
+    }
+
     unsafe extern "C" fn send_header(
         sapi_header: *mut crate::sys::sapi_header_struct,
         server_context: *mut std::ffi::c_void,
@@ -226,9 +283,13 @@ unsafe impl<T: Sapi> RawPhpSapi for T {
                     return;
                 }
                 let cstr = CStr::from_ptr(sapi_header.header);
-                cstr.to_str().unwrap()
+                cstr.to_string_lossy()
             };
-            T::send_header(header.to_string(), ctx.map(|mut ctx| ctx.as_mut()));
+            if let Some(mut ctx) = ctx {
+                T::send_header(ctx.as_mut(), header.into_owned());
+            } else {
+                tracing::debug!("server_context is null");
+            }
         } else {
             tracing::debug!("sapi_header is null");
         }
@@ -236,38 +297,47 @@ unsafe impl<T: Sapi> RawPhpSapi for T {
 
     unsafe extern "C" fn read_post(buffer: *mut std::ffi::c_char, count: usize) -> usize {
         let slice = unsafe { std::slice::from_raw_parts_mut(buffer as *mut u8, count) };
-        T::read_post(slice)
+        if let Some(ctx) = server_context::<T>() {
+            T::read_post(ctx, slice)
+        } else {
+            tracing::debug!("server_context is null");
+            0
+        }
     }
 
     unsafe extern "C" fn read_cookies() -> *mut std::ffi::c_char {
-        let cookies = T::read_cookies();
-        let cookie_mem = unsafe {
-            std::alloc::alloc(
-                std::alloc::Layout::from_size_align(cookies.len() + 1, std::mem::align_of::<u8>())
-                    .expect("Failed to create layout"),
-            )
+        let Some(ctx) = server_context::<T>() else {
+            tracing::debug!("server_context is null");
+            return std::ptr::null_mut();
         };
-        std::ptr::copy(cookies.as_ptr(), cookie_mem, cookies.len());
-        std::ptr::write(cookie_mem.add(cookies.len()), 0);
-        cookie_mem as *mut std::ffi::c_char
+        let cookies = CString::new(T::read_cookies(ctx)).unwrap_or_default();
+        COOKIE_DATA.with_borrow_mut(|value| value.insert(cookies).as_ptr() as *mut std::ffi::c_char)
     }
 
     unsafe extern "C" fn register_server_variables(track_vars_array: *mut crate::sys::zval) -> () {
         let mut track_vars_array = TrackVarsArray::new(NonNull::new(track_vars_array).unwrap());
-        T::register_server_variables(&mut track_vars_array)
+        if let Some(ctx) = server_context::<T>() {
+            T::register_server_variables(ctx, &mut track_vars_array)
+        } else {
+            tracing::debug!("server_context is null");
+        }
     }
 
     unsafe extern "C" fn get_request_time(
         req_time: *mut std::ffi::c_double,
     ) -> crate::sys::ZEND_RESULT_CODE {
+        let Some(ctx) = server_context::<T>() else {
+            // PHP falls back to the current time.
+            return crate::sys::ZEND_RESULT_CODE_FAILURE;
+        };
         unsafe {
-            *req_time = T::get_request_time();
+            *req_time = T::get_request_time(ctx);
         }
         crate::sys::ZEND_RESULT_CODE_SUCCESS
     }
 
     unsafe extern "C" fn terminate_process() -> () {
-        todo!("terminate_process not implemented");
+        T::terminate_process()
     }
 
     unsafe extern "C" fn log_message(
@@ -275,55 +345,48 @@ unsafe impl<T: Sapi> RawPhpSapi for T {
         syslog_type_int: std::ffi::c_int,
     ) -> () {
         let slice = unsafe { std::ffi::CStr::from_ptr(message) };
-        let string = slice.to_str().unwrap();
-        T::log_message(string, syslog_type_int)
+        let string = slice.to_string_lossy();
+        if let Some(ctx) = server_context::<T>() {
+            T::log_message(ctx, &string, syslog_type_int)
+        } else {
+            log_to_tracing(&string, syslog_type_int);
+        }
     }
-    
-    fn on_before_request_init() {
-        T::on_before_request_init();
+
+    fn on_before_request_init(ctx: &mut Self::Context) {
+        T::on_before_request_init(ctx);
     }
-}
 
-/*fn load_sapi_headers(sapi_headers: *mut sapi_headers_struct) -> Headers {
-    if sapi_headers.is_null() {
-        panic!("sapi_headers is null");
+    fn on_request_start(ctx: &mut Self::Context) {
+        T::on_request_start(ctx);
     }
-    let headers = unsafe { &mut *sapi_headers };
+}
+
+/// Read the status and headers PHP is about to send.
+unsafe fn load_sapi_headers(sapi_headers: *mut sapi_headers_struct) -> Headers {
+    let headers = &mut *sapi_headers;
     let mut headers_vec = Vec::new();
     let mut pos: zend_llist_position = std::ptr::null_mut();
-    let mut tmp_entry =
-        unsafe { zend_llist_get_first_ex(&mut headers.headers, &mut pos) } as *mut zval;
+    let mut entry =
+        zend_llist_get_first_ex(&mut headers.headers, &mut pos) as *mut sapi_header_struct;
 
-    while !tmp_entry.is_null() {
-        let header = unsafe {
-            let cstr = CStr::from_ptr(libphp_zval_get_string(tmp_entry));
-            cstr.to_str().unwrap()
-        };
-        headers_vec.push(header.to_string());
-        tmp_entry = unsafe { zend_llist_get_next_ex(&mut headers.headers, &mut pos) as *mut zval };
+    while let Some(header) = entry.as_ref() {
+        if !header.header.is_null() {
+            let bytes = std::slice::from_raw_parts(header.header as *const u8, header.header_len);
+            headers_vec.push(String::from_utf8_lossy(bytes).into_owned());
+        }
+        entry = zend_llist_get_next_ex(&mut headers.headers, &mut pos) as *mut sapi_header_struct;
     }
 
+    let string = |ptr: *mut std::ffi::c_char| {
+        (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    };
+
     Headers {
         headers: headers_vec,
         http_response_code: headers.http_response_code,
         send_default_content_type: headers.send_default_content_type != 0,
-        mime_type: if headers.mimetype.is_null() {
-            None
-        } else {
-            unsafe {
-                Some(std::ffi::CStr::from_ptr(headers.mimetype)
-                    .to_string_lossy()
-                    .into_owned())
-            }
-        },
-        http_status_line: if headers.http_status_line.is_null() {
-            None
-        } else {
-            unsafe {
-                Some(std::ffi::CStr::from_ptr(headers.http_status_line)
-                    .to_string_lossy()
-                    .into_owned())
-            }
-        },
+        mime_type: string(headers.mimetype),
+        http_status_line: string(headers.http_status_line),
     }
-}*/
+}
@@ -0,0 +1,295 @@
+//! Run PHP in separate worker processes, so a crash only takes down the worker.
+//!
+//! The [`Supervisor`] starts `libphp-worker` processes (see [`run_worker`]) and sends them
+//! jobs over a socket they inherit. Workers that die are replaced, and workers are recycled after a
+//! number of jobs or once they use too much memory, like php-fpm does.
+
+use std::{
+    env,
+    fmt::Display,
+    io::{self, ErrorKind},
+    os::unix::{io::AsRawFd, net::UnixStream, process::CommandExt},
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::value::OwnedValue;
+
+mod protocol;
+mod worker;
+
+pub use protocol::{Request, Response};
+pub use worker::run_worker;
+
+/// How the supervisor runs its workers.
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    /// The worker executable, which is passed the file descriptor of its socket.
+    pub worker_program: PathBuf,
+    /// The maximum number of worker processes.
+    pub workers: usize,
+    /// Replace a worker after it has run this many jobs.
+    pub max_requests: Option<u64>,
+    /// Replace a worker once its resident memory exceeds this many bytes after a job.
+    ///
+    /// Only Linux reports the memory of a worker, so this has no effect elsewhere.
+    pub max_memory: Option<u64>,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        // Binaries of the same package are placed next to each other.
+        let worker_program = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join("libphp-worker")))
+            .unwrap_or_else(|| PathBuf::from("libphp-worker"));
+
+        Self {
+            worker_program,
+            workers: 4,
+            max_requests: None,
+            max_memory: None,
+        }
+    }
+}
+
+/// Why a job sent to a worker did not produce a result.
+#[derive(Debug)]
+pub enum WorkerError {
+    /// A worker could not be started.
+    Spawn(io::Error),
+    /// The worker died while running the job.
+    Crashed(ExitStatus),
+    /// Talking to the worker failed. The worker has been stopped.
+    Io(io::Error),
+}
+
+impl Display for WorkerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spawn(err) => write!(f, "Failed to start PHP worker: {}", err),
+            Self::Crashed(status) => write!(f, "PHP worker crashed ({})", status),
+            Self::Io(err) => write!(f, "Failed to communicate with PHP worker: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for WorkerError {}
+
+struct Worker {
+    child: Child,
+    stream: UnixStream,
+    requests: u64,
+}
+
+impl Worker {
+    /// Wait for the worker to exit, killing it if it doesn't within `timeout`.
+    fn stop(mut self, timeout: Duration) -> io::Result<ExitStatus> {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+        let started = Instant::now();
+
+        while started.elapsed() < timeout {
+            match self.child.try_wait() {
+                Ok(Some(status)) => return Ok(status),
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+                Err(_) => break,
+            }
+        }
+
+        let _ = self.child.kill();
+        self.child.wait()
+    }
+
+    /// Stop the worker when nothing waits for its exit status.
+    fn stop_quietly(self, timeout: Duration) {
+        if let Err(err) = self.stop(timeout) {
+            tracing::warn!("Failed to wait for PHP worker: {}", err);
+        }
+    }
+}
+
+struct Workers {
+    idle: Vec<Worker>,
+    live: usize,
+}
+
+/// Runs PHP jobs in a set of worker processes.
+///
+/// Jobs can be submitted from several threads at once; each is sent to an idle worker,
+/// waiting for one if all are busy.
+pub struct Supervisor {
+    config: SupervisorConfig,
+    workers: Mutex<Workers>,
+    available: Condvar,
+}
+
+impl Supervisor {
+    /// Start a supervisor and all of its workers.
+    pub fn new(config: SupervisorConfig) -> Result<Self, WorkerError> {
+        let supervisor = Self {
+            config,
+            workers: Mutex::new(Workers {
+                idle: Vec::new(),
+                live: 0,
+            }),
+            available: Condvar::new(),
+        };
+
+        for _ in 0..supervisor.config.workers {
+            let worker = supervisor.spawn().map_err(WorkerError::Spawn)?;
+            let mut workers = supervisor.lock();
+            workers.idle.push(worker);
+            workers.live += 1;
+        }
+
+        Ok(supervisor)
+    }
+
+    /// Execute a PHP file in a worker.
+    pub fn execute_file(&self, file: impl Into<String>) -> Result<Response, WorkerError> {
+        self.run(&Request::ExecuteFile(file.into()))
+    }
+
+    /// Call a PHP function in a worker.
+    pub fn call(
+        &self,
+        name: impl Into<String>,
+        args: Vec<OwnedValue>,
+    ) -> Result<Response, WorkerError> {
+        self.run(&Request::Call(name.into(), args))
+    }
+
+    /// Evaluate a PHP expression in a worker.
+    pub fn eval(&self, code: impl Into<String>) -> Result<Response, WorkerError> {
+        self.run(&Request::Eval(code.into()))
+    }
+
+    /// Send a job to a worker and wait for its result.
+    pub fn run(&self, request: &Request) -> Result<Response, WorkerError> {
+        let mut worker = self.checkout()?;
+
+        let result = request
+            .write(&mut worker.stream)
+            .and_then(|_| Response::read(&mut worker.stream));
+
+        let response = match result {
+            Ok(response) => response,
+            Err(err) => {
+                let exited = worker.child.try_wait().ok().flatten().is_some()
+                    || err.kind() == ErrorKind::UnexpectedEof;
+                let status = worker.stop(Duration::from_millis(100));
+                self.retire();
+
+                tracing::debug!("PHP worker failed: {}", err);
+
+                return Err(match status {
+                    Ok(status) if exited => WorkerError::Crashed(status),
+                    Ok(_) => WorkerError::Io(err),
+                    Err(wait_err) => {
+                        tracing::warn!("Failed to wait for PHP worker: {}", wait_err);
+                        WorkerError::Io(err)
+                    }
+                });
+            }
+        };
+
+        worker.requests += 1;
+
+        let recycle = self
+            .config
+            .max_requests
+            .is_some_and(|max| worker.requests >= max)
+            || self
+                .config
+                .max_memory
+                .is_some_and(|max| response.memory > max);
+
+        if recycle {
+            worker.stop_quietly(Duration::from_secs(1));
+            self.retire();
+        } else {
+            self.lock().idle.push(worker);
+            self.available.notify_one();
+        }
+
+        Ok(response)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Workers> {
+        self.workers.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn checkout(&self) -> Result<Worker, WorkerError> {
+        let mut workers = self.lock();
+
+        loop {
+            if let Some(worker) = workers.idle.pop() {
+                return Ok(worker);
+            }
+
+            // Replace workers that crashed or were recycled.
+            if workers.live < self.config.workers.max(1) {
+                workers.live += 1;
+                drop(workers);
+
+                return self.spawn().map_err(|err| {
+                    self.retire();
+                    WorkerError::Spawn(err)
+                });
+            }
+
+            workers = self
+                .available
+                .wait(workers)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    fn retire(&self) {
+        self.lock().live -= 1;
+        self.available.notify_one();
+    }
+
+    fn spawn(&self) -> io::Result<Worker> {
+        // The sockets are connected already, so there is no named socket another process
+        // could get to first.
+        let (stream, worker_stream) = UnixStream::pair()?;
+        let fd = worker_stream.as_raw_fd();
+
+        let mut command = Command::new(&self.config.worker_program);
+        command.arg(fd.to_string()).stdin(Stdio::null());
+
+        // Every descriptor Rust opens is closed on exec, so the worker's end is kept open
+        // in the worker only. This runs in the forked process, where only
+        // async-signal-safe calls are allowed.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let child = command.spawn()?;
+
+        Ok(Worker {
+            child,
+            stream,
+            requests: 0,
+        })
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        let idle = std::mem::take(&mut self.lock().idle);
+
+        for worker in idle {
+            worker.stop_quietly(Duration::from_secs(1));
+        }
+    }
+}
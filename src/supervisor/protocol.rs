@@ -0,0 +1,340 @@
+//! The messages exchanged between the supervisor and its workers.
+//!
+//! Every message is a frame: a little-endian `u32` length followed by that many bytes of
+//! payload. Values are encoded with a one-byte tag followed by their contents, with strings
+//! and lists prefixed by a `u32` length.
+
+use std::io::{self, Read, Write};
+
+use crate::{
+    diagnostic::{ErrorLevel, PhpDiagnostic},
+    error::PhpError,
+    value::{array::ArrayKey, OwnedValue},
+};
+
+// Frames larger than this are rejected rather than allocated.
+const MAX_FRAME_LEN: usize = 256 * 1024 * 1024;
+
+/// A job sent to a worker.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    ExecuteFile(String),
+    Call(String, Vec<OwnedValue>),
+    Eval(String),
+}
+
+/// The result of a job, sent back by the worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// The value the job returned, or the error that aborted it.
+    pub value: Result<OwnedValue, PhpError>,
+    /// Everything the job wrote to the output.
+    pub output: Vec<u8>,
+    /// The resident memory of the worker process after the job, in bytes, or 0 where it is
+    /// not known.
+    pub memory: u64,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(invalid("Truncated message"));
+        }
+
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?).map_err(|_| invalid("String is not valid UTF-8"))
+    }
+
+    fn value(&mut self) -> io::Result<OwnedValue> {
+        Ok(match self.u8()? {
+            0 => OwnedValue::Null,
+            1 => OwnedValue::Bool(false),
+            2 => OwnedValue::Bool(true),
+            3 => OwnedValue::Int(self.u64()? as i64),
+            4 => OwnedValue::Float(f64::from_bits(self.u64()?)),
+            5 => OwnedValue::String(self.bytes()?),
+            6 => {
+                let len = self.u32()?;
+                let mut entries = Vec::new();
+
+                for _ in 0..len {
+                    let key = match self.u8()? {
+                        0 => ArrayKey::Int(self.u64()? as i64),
+                        1 => ArrayKey::String(self.string()?),
+                        _ => return Err(invalid("Unknown array key type")),
+                    };
+
+                    entries.push((key, self.value()?));
+                }
+
+                OwnedValue::Array(entries)
+            }
+            _ => return Err(invalid("Unknown value type")),
+        })
+    }
+
+    fn diagnostic(&mut self) -> io::Result<PhpDiagnostic> {
+        Ok(PhpDiagnostic {
+            level: ErrorLevel(self.u32()? as i32),
+            message: self.string()?,
+            file: match self.u8()? {
+                0 => None,
+                _ => Some(self.string()?),
+            },
+            line: self.u32()?,
+        })
+    }
+
+    fn result(&mut self) -> io::Result<Result<OwnedValue, PhpError>> {
+        Ok(match self.u8()? {
+            0 => Ok(self.value()?),
+            1 => Err(PhpError::Fatal(self.diagnostic()?)),
+            2 => Err(PhpError::Timeout),
+            3 => Err(PhpError::OutOfMemory),
+            4 => Err(PhpError::Interrupted),
+            5 => Err(PhpError::Exit(self.u32()? as i32)),
+            6 => Err(PhpError::Escalated(self.diagnostic()?)),
+            7 => Err(PhpError::Ended),
+            _ => return Err(invalid("Unknown result type")),
+        })
+    }
+
+    fn finish(self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("Trailing bytes after message"))
+        }
+    }
+}
+
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn value(&mut self, value: &OwnedValue) {
+        match value {
+            OwnedValue::Null => self.u8(0),
+            OwnedValue::Bool(false) => self.u8(1),
+            OwnedValue::Bool(true) => self.u8(2),
+            OwnedValue::Int(i) => {
+                self.u8(3);
+                self.u64(*i as u64);
+            }
+            OwnedValue::Float(f) => {
+                self.u8(4);
+                self.u64(f.to_bits());
+            }
+            OwnedValue::String(bytes) => {
+                self.u8(5);
+                self.bytes(bytes);
+            }
+            OwnedValue::Array(entries) => {
+                self.u8(6);
+                self.u32(entries.len() as u32);
+
+                for (key, value) in entries {
+                    match key {
+                        ArrayKey::Int(index) => {
+                            self.u8(0);
+                            self.u64(*index as u64);
+                        }
+                        ArrayKey::String(key) => {
+                            self.u8(1);
+                            self.bytes(key.as_bytes());
+                        }
+                    }
+
+                    self.value(value);
+                }
+            }
+        }
+    }
+
+    fn diagnostic(&mut self, diagnostic: &PhpDiagnostic) {
+        self.u32(diagnostic.level.0 as u32);
+        self.bytes(diagnostic.message.as_bytes());
+
+        match &diagnostic.file {
+            Some(file) => {
+                self.u8(1);
+                self.bytes(file.as_bytes());
+            }
+            None => self.u8(0),
+        }
+
+        self.u32(diagnostic.line);
+    }
+
+    fn result(&mut self, result: &Result<OwnedValue, PhpError>) {
+        match result {
+            Ok(value) => {
+                self.u8(0);
+                self.value(value);
+            }
+            Err(PhpError::Fatal(diagnostic)) => {
+                self.u8(1);
+                self.diagnostic(diagnostic);
+            }
+            Err(PhpError::Timeout) => self.u8(2),
+            Err(PhpError::OutOfMemory) => self.u8(3),
+            Err(PhpError::Interrupted) => self.u8(4),
+            Err(PhpError::Exit(status)) => {
+                self.u8(5);
+                self.u32(*status as u32);
+            }
+            Err(PhpError::Escalated(diagnostic)) => {
+                self.u8(6);
+                self.diagnostic(diagnostic);
+            }
+            Err(PhpError::Ended) => self.u8(7),
+        }
+    }
+}
+
+fn write_frame(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(invalid("Message too large"));
+    }
+
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid("Message too large"));
+    }
+
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+impl Request {
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut encoder = Encoder::default();
+
+        match self {
+            Self::ExecuteFile(file) => {
+                encoder.u8(0);
+                encoder.bytes(file.as_bytes());
+            }
+            Self::Call(name, args) => {
+                encoder.u8(1);
+                encoder.bytes(name.as_bytes());
+                encoder.u32(args.len() as u32);
+                args.iter().for_each(|arg| encoder.value(arg));
+            }
+            Self::Eval(code) => {
+                encoder.u8(2);
+                encoder.bytes(code.as_bytes());
+            }
+        }
+
+        write_frame(writer, &encoder.buf)
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let payload = read_frame(reader)?;
+        let mut decoder = Decoder { buf: &payload };
+
+        let request = match decoder.u8()? {
+            0 => Self::ExecuteFile(decoder.string()?),
+            1 => {
+                let name = decoder.string()?;
+                let len = decoder.u32()?;
+                let args = (0..len)
+                    .map(|_| decoder.value())
+                    .collect::<io::Result<_>>()?;
+
+                Self::Call(name, args)
+            }
+            2 => Self::Eval(decoder.string()?),
+            _ => return Err(invalid("Unknown request type")),
+        };
+
+        decoder.finish()?;
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut encoder = Encoder::default();
+
+        encoder.result(&self.value);
+        encoder.bytes(&self.output);
+        encoder.u64(self.memory);
+
+        write_frame(writer, &encoder.buf)
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let payload = read_frame(reader)?;
+        let mut decoder = Decoder { buf: &payload };
+
+        let response = Self {
+            value: decoder.result()?,
+            output: decoder.bytes()?,
+            memory: decoder.u64()?,
+        };
+
+        decoder.finish()?;
+        Ok(response)
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    io::{self, ErrorKind},
+    os::unix::net::UnixStream,
+};
+
+use crate::{exec::Engine, value::OwnedValue};
+
+use super::protocol::{Request, Response};
+
+/// Get the resident memory of the current process in bytes, or 0 if it is not known.
+#[cfg(target_os = "linux")]
+fn resident_memory() -> u64 {
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        return 0;
+    };
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rss| rss.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or(0, |kb| kb * 1024)
+}
+
+/// Other systems have no `/proc/self/status`, and `getrusage` only reports the peak.
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> u64 {
+    0
+}
+
+/// Serve jobs from the supervisor at the other end of `stream` until it disconnects.
+///
+/// Every job runs in a fresh request, and its output is sent back instead of being written
+/// to stdout.
+pub fn run_worker(mut stream: UnixStream) -> io::Result<()> {
+    let engine = Engine::boot();
+
+    loop {
+        let request = match Request::read(&mut stream) {
+            Ok(request) => request,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        let (value, output) = engine.request(move |ctx| {
+            ctx.capture_output(|ctx| {
+                match &request {
+                    Request::ExecuteFile(file) => ctx.execute_file(file, false),
+                    Request::Call(name, args) if args.is_empty() => ctx.call(name),
+                    Request::Call(name, args) => ctx.call_with(name, args),
+                    Request::Eval(code) => ctx.result_of(code, false),
+                }
+                .map(OwnedValue::from)
+            })
+        });
+
+        Response {
+            value,
+            output,
+            memory: resident_memory(),
+        }
+        .write(&mut stream)?;
+    }
+}
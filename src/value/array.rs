@@ -77,6 +77,7 @@ impl<'a> ArrayIter<'a> {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ArrayKey {
     Int(i64),
     String(String),
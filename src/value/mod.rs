@@ -14,8 +14,10 @@ use self::array::Array;
 
 pub mod array;
 mod int;
+mod owned;
 mod string;
 
+pub use owned::OwnedValue;
 pub use string::create_zend_str;
 
 #[derive(Clone)]
@@ -47,6 +49,11 @@ impl InnerValue {
     }
 }
 
+/// A PHP value, which belongs to the request it was created in.
+///
+/// Values are not `Send`, so they cannot be returned from
+/// [`Engine::request`](crate::exec::Engine::request) and dropped after the request has ended.
+/// Convert them to an [`OwnedValue`] to keep them.
 pub struct Value {
     inner: InnerValue,
 }
@@ -0,0 +1,179 @@
+use std::ptr::NonNull;
+
+use crate::sys::{
+    libphp_hash_index_update, libphp_hash_str_update, libphp_zval_copy_deref,
+    libphp_zval_create_array, libphp_zval_create_bool, libphp_zval_create_double,
+    libphp_zval_create_long, libphp_zval_create_null, libphp_zval_create_stringl,
+    libphp_zval_get_stringl, libphp_zval_get_type, zend_hash_get_current_data_ex,
+    zend_hash_get_current_key_type_ex, zend_hash_get_current_key_zval_ex,
+    zend_hash_move_forward_ex, zval, zval_ptr_dtor, HashTable, HASH_KEY_NON_EXISTENT, IS_ARRAY,
+    IS_DOUBLE, IS_FALSE, IS_LONG, IS_NULL, IS_STRING, IS_TRUE,
+};
+
+use super::{array::ArrayKey, Value};
+
+/// A copy of a PHP value that lives outside of any request.
+///
+/// Unlike [`Value`], an owned value can be kept after the request it came from has ended
+/// and sent to other threads. Only scalars, strings and arrays can be copied; other values
+/// (objects, resources) become `Null`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(Vec<u8>),
+    Array(Vec<(ArrayKey, OwnedValue)>),
+}
+
+impl OwnedValue {
+    /// Get the value as a string, if it is one.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(bytes) => std::str::from_utf8(bytes).ok(),
+            _ => None,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `zval` must point to a valid, dereferenced zval.
+    unsafe fn from_zval(zval: *const zval) -> Self {
+        match libphp_zval_get_type(zval) {
+            IS_NULL => Self::Null,
+            IS_FALSE => Self::Bool(false),
+            IS_TRUE => Self::Bool(true),
+            IS_LONG => Self::Int((*zval).value.lval),
+            IS_DOUBLE => Self::Float((*zval).value.dval),
+            IS_STRING => {
+                // Strings are not converted, so the zval is not modified.
+                let mut len = 0;
+                let str = libphp_zval_get_stringl(zval as *mut zval, &mut len);
+                Self::String(std::slice::from_raw_parts(str as *const u8, len).to_vec())
+            }
+            IS_ARRAY => Self::Array(Self::entries((*zval).value.arr)),
+            _ => Self::Null,
+        }
+    }
+
+    unsafe fn entries(table: *mut HashTable) -> Vec<(ArrayKey, OwnedValue)> {
+        let mut entries = Vec::new();
+        let mut pos = 0;
+
+        while zend_hash_get_current_key_type_ex(table, &mut pos) != HASH_KEY_NON_EXISTENT {
+            let mut key = zval::default();
+            let mut value = zval::default();
+
+            zend_hash_get_current_key_zval_ex(table, &mut key, &pos);
+
+            let data = NonNull::new(zend_hash_get_current_data_ex(table, &mut pos));
+            zend_hash_move_forward_ex(table, &mut pos);
+
+            if data.is_some_and(|data| libphp_zval_copy_deref(&mut value, data.as_ptr())) {
+                let key = match Self::from_zval(&key) {
+                    Self::Int(index) => ArrayKey::Int(index),
+                    key => ArrayKey::String(key.as_str().unwrap_or_default().to_string()),
+                };
+
+                entries.push((key, Self::from_zval(&value)));
+                zval_ptr_dtor(&mut value);
+            }
+
+            zval_ptr_dtor(&mut key);
+        }
+
+        entries
+    }
+}
+
+impl From<&Value> for OwnedValue {
+    fn from(value: &Value) -> Self {
+        unsafe { Self::from_zval(value.as_ptr()) }
+    }
+}
+
+impl From<Value> for OwnedValue {
+    fn from(value: Value) -> Self {
+        (&value).into()
+    }
+}
+
+impl From<&OwnedValue> for Value {
+    fn from(value: &OwnedValue) -> Self {
+        let mut zval = zval::default();
+
+        unsafe {
+            match value {
+                OwnedValue::Null => libphp_zval_create_null(&mut zval),
+                OwnedValue::Bool(b) => libphp_zval_create_bool(&mut zval, *b),
+                OwnedValue::Int(i) => {
+                    libphp_zval_create_long(&mut zval, *i);
+                }
+                OwnedValue::Float(f) => libphp_zval_create_double(&mut zval, *f),
+                OwnedValue::String(bytes) => libphp_zval_create_stringl(
+                    &mut zval,
+                    bytes.as_ptr() as *const std::ffi::c_char,
+                    bytes.len(),
+                ),
+                OwnedValue::Array(entries) => {
+                    let table = libphp_zval_create_array(&mut zval, entries.len() as u32);
+
+                    for (key, value) in entries {
+                        let mut value = Value::from(value);
+
+                        match key {
+                            ArrayKey::Int(index) => {
+                                libphp_hash_index_update(table, *index, value.as_mut_ptr())
+                            }
+                            ArrayKey::String(key) => libphp_hash_str_update(
+                                table,
+                                key.as_ptr() as *const std::ffi::c_char,
+                                key.len(),
+                                value.as_mut_ptr(),
+                            ),
+                        }
+                    }
+                }
+            }
+        }
+
+        Self::new(&zval)
+    }
+}
+
+impl From<OwnedValue> for Value {
+    fn from(value: OwnedValue) -> Self {
+        (&value).into()
+    }
+}
+
+impl From<bool> for OwnedValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i64> for OwnedValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<f64> for OwnedValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<&str> for OwnedValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for OwnedValue {
+    fn from(value: String) -> Self {
+        Self::String(value.into_bytes())
+    }
+}
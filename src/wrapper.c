@@ -10,6 +10,13 @@ const char *libphp_zval_get_string(zval *pz)
     return Z_STRVAL_P(pz);
 }
 
+const char *libphp_zval_get_stringl(zval *pz, size_t *len)
+{
+    convert_to_string(pz);
+    *len = Z_STRLEN_P(pz);
+    return Z_STRVAL_P(pz);
+}
+
 zend_string* libphp_zend_string_init(const char *str)
 {
     return zend_string_init(str, strlen(str), 0);
@@ -37,6 +44,32 @@ void libphp_zval_create_long(zval *pz, long l)
     ZVAL_LONG(pz, l);
 }
 
+void libphp_zval_create_null(zval *pz)
+{
+    ZVAL_NULL(pz);
+}
+
+void libphp_zval_create_bool(zval *pz, bool b)
+{
+    ZVAL_BOOL(pz, b);
+}
+
+void libphp_zval_create_double(zval *pz, double d)
+{
+    ZVAL_DOUBLE(pz, d);
+}
+
+void libphp_zval_create_stringl(zval *pz, const char *str, size_t len)
+{
+    ZVAL_STRINGL_FAST(pz, str, len);
+}
+
+HashTable *libphp_zval_create_array(zval *pz, uint32_t size)
+{
+    array_init_size(pz, size);
+    return Z_ARRVAL_P(pz);
+}
+
 void libphp_register_variable(const char *key, zval *value)
 {
     zend_hash_str_update(&EG(symbol_table), key, strlen(key), value);
@@ -54,6 +87,157 @@ void libphp_register_constant(const char *name, zval *value)
     zend_register_constant(&c);
 }
 
+HashTable *libphp_symbol_table(void)
+{
+	return &EG(symbol_table);
+}
+
+/* Copy a symbol table slot into dst, following IS_INDIRECT (compiled
+ * variables of the running script) and references. Returns false if the
+ * slot holds an unset variable. */
+bool libphp_zval_copy_deref(zval *dst, zval *src)
+{
+	if (Z_TYPE_P(src) == IS_INDIRECT) {
+		src = Z_INDIRECT_P(src);
+	}
+	if (Z_TYPE_P(src) == IS_UNDEF) {
+		return false;
+	}
+	ZVAL_COPY_DEREF(dst, src);
+	return true;
+}
+
+bool libphp_global_get(const char *name, size_t name_len, zval *retval)
+{
+	zval *var = zend_hash_str_find(&EG(symbol_table), name, name_len);
+
+	if (var == NULL) {
+		return false;
+	}
+	return libphp_zval_copy_deref(retval, var);
+}
+
+void libphp_global_set(const char *name, size_t name_len, zval *value)
+{
+	zval *var = zend_hash_str_find(&EG(symbol_table), name, name_len);
+
+	if (var != NULL && Z_TYPE_P(var) == IS_INDIRECT) {
+		var = Z_INDIRECT_P(var);
+	}
+	if (var != NULL && Z_TYPE_P(var) != IS_UNDEF) {
+		zval garbage;
+
+		ZVAL_DEREF(var);
+		ZVAL_COPY_VALUE(&garbage, var);
+		ZVAL_COPY(var, value);
+		zval_ptr_dtor(&garbage);
+	} else if (var != NULL) {
+		ZVAL_COPY(var, value);
+	} else {
+		zval copy;
+
+		ZVAL_COPY(&copy, value);
+		zend_hash_str_update(&EG(symbol_table), name, name_len, &copy);
+	}
+}
+
+void libphp_hash_str_update(HashTable *ht, const char *key, size_t key_len, zval *value)
+{
+	zval copy;
+
+	ZVAL_COPY(&copy, value);
+	zend_hash_str_update(ht, key, key_len, &copy);
+}
+
+void libphp_hash_index_update(HashTable *ht, zend_long index, zval *value)
+{
+	zval copy;
+
+	ZVAL_COPY(&copy, value);
+	zend_hash_index_update(ht, index, &copy);
+}
+
+bool libphp_global_unset(const char *name, size_t name_len)
+{
+	return zend_hash_str_del_ind(&EG(symbol_table), name, name_len) == SUCCESS;
+}
+
+/* Start an output buffer, like ob_start(). Returns its nesting level, or 0 if
+ * output could not be buffered. */
+int libphp_output_capture_start(void)
+{
+	if (php_output_start_default() == FAILURE) {
+		return 0;
+	}
+	return php_output_get_level();
+}
+
+/* Remove the buffer started at `level` and store everything written to it in
+ * `contents`. Buffers the script left open on top of it are flushed into it
+ * first; if the script removed it, `contents` is empty. */
+void libphp_output_capture_end(int level, zval *contents)
+{
+	ZVAL_EMPTY_STRING(contents);
+
+	if (level == 0 || php_output_get_level() < level) {
+		return;
+	}
+	while (php_output_get_level() > level) {
+		php_output_end();
+	}
+	php_output_get_contents(contents);
+	php_output_discard();
+}
+
+struct libphp_output_sink {
+	size_t (*write)(void *data, const char *buf, size_t len);
+	void (*dtor)(void *data);
+	void *data;
+};
+
+static zend_result libphp_output_sink_handler(void **handler_context, php_output_context *output_context)
+{
+	struct libphp_output_sink *sink = *handler_context;
+
+	if (output_context->in.used) {
+		sink->write(sink->data, output_context->in.data, output_context->in.used);
+	}
+	/* Produce no output: everything went to the sink. */
+	return SUCCESS;
+}
+
+static void libphp_output_sink_dtor(void *opaq)
+{
+	struct libphp_output_sink *sink = opaq;
+
+	sink->dtor(sink->data);
+	efree(sink);
+}
+
+/* Send all output of the current request to `write` instead of the SAPI. The
+ * handler can't be removed by the script; `dtor` is called with `data` when
+ * the request ends, or right away if the handler could not be started. */
+bool libphp_output_start_sink(size_t (*write)(void *data, const char *buf, size_t len), void (*dtor)(void *data), void *data)
+{
+	php_output_handler *handler;
+	struct libphp_output_sink *sink;
+
+	handler = php_output_handler_create_internal(ZEND_STRL("libphp output sink"),
+		libphp_output_sink_handler, 1, PHP_OUTPUT_HANDLER_CLEANABLE | PHP_OUTPUT_HANDLER_FLUSHABLE);
+
+	sink = emalloc(sizeof(struct libphp_output_sink));
+	sink->write = write;
+	sink->dtor = dtor;
+	sink->data = data;
+	php_output_handler_set_context(handler, sink, libphp_output_sink_dtor);
+
+	if (php_output_handler_start(handler) == FAILURE) {
+		php_output_handler_free(&handler);
+		return false;
+	}
+	return true;
+}
+
 uint32_t libphp_zval_addref_p(zval* pz) {
 	return Z_ADDREF_P(pz);
 }
@@ -87,7 +271,15 @@ static zend_always_inline void i_init_code_execute_data(zend_execute_data *execu
 	EG(current_execute_data) = execute_data;
 }
 
-void libphp_execute(zend_op_array *op_array, zval *return_value, bool reset_global_ctx)
+static zend_array *libphp_target_symbol_table(bool reset_global_ctx)
+{
+	if (EG(current_execute_data) && reset_global_ctx) {
+		return zend_rebuild_symbol_table();
+	}
+	return &EG(symbol_table);
+}
+
+void libphp_execute_in(zend_op_array *op_array, zval *return_value, zend_array *symbol_table)
 {
 	zend_execute_data *execute_data;
 	void *object_or_called_scope;
@@ -106,11 +298,7 @@ void libphp_execute(zend_op_array *op_array, zval *return_value, bool reset_glob
 	}
 	execute_data = zend_vm_stack_push_call_frame(call_info,
 		(zend_function*)op_array, 0, object_or_called_scope);
-	if (EG(current_execute_data) && reset_global_ctx) {
-		execute_data->symbol_table = zend_rebuild_symbol_table();
-	} else {
-		execute_data->symbol_table = &EG(symbol_table);
-	}
+	execute_data->symbol_table = symbol_table;
 	EX(prev_execute_data) = EG(current_execute_data);
 	i_init_code_execute_data(execute_data, op_array, return_value);
 	ZEND_OBSERVER_FCALL_BEGIN(execute_data);
@@ -119,9 +307,14 @@ void libphp_execute(zend_op_array *op_array, zval *return_value, bool reset_glob
 	zend_vm_stack_free_call_frame(execute_data);
 }
 
+void libphp_execute(zend_op_array *op_array, zval *return_value, bool reset_global_ctx)
+{
+	libphp_execute_in(op_array, return_value, libphp_target_symbol_table(reset_global_ctx));
+}
+
 
 
-zend_result libphp_eval_stringl(const char *str, size_t str_len, zval *retval_ptr, const char *string_name, bool reset_global_ctx) /* {{{ */
+static zend_result libphp_eval_stringl_in(const char *str, size_t str_len, zval *retval_ptr, const char *string_name, zend_array *symbol_table)
 {
 	zend_op_array *new_op_array;
 	uint32_t original_compiler_options;
@@ -151,7 +344,7 @@ zend_result libphp_eval_stringl(const char *str, size_t str_len, zval *retval_pt
 
 		zend_try {
 			ZVAL_UNDEF(&local_retval);
-			libphp_execute(new_op_array, &local_retval, reset_global_ctx);
+			libphp_execute_in(new_op_array, &local_retval, symbol_table);
 		} zend_catch {
 			destroy_op_array(new_op_array);
 			efree_size(new_op_array, sizeof(zend_op_array));
@@ -182,17 +375,330 @@ zend_result libphp_eval_stringl(const char *str, size_t str_len, zval *retval_pt
 	return retval;
 }
 
+zend_result libphp_eval_stringl(const char *str, size_t str_len, zval *retval_ptr, const char *string_name, bool reset_global_ctx)
+{
+	return libphp_eval_stringl_in(str, str_len, retval_ptr, string_name, libphp_target_symbol_table(reset_global_ctx));
+}
+
+/* Evaluate an expression with the given table as its (only) local scope. The
+ * compiled variables are written back to the table once execution finishes.
+ * If the code bails out, the table is emptied instead. */
+zend_result libphp_eval_stringl_scoped_ex(const char *str, size_t str_len, zval *retval_ptr, const char *string_name, HashTable *scope)
+{
+	zend_result result = FAILURE;
+
+	zend_try {
+		result = libphp_eval_stringl_in(str, str_len, retval_ptr, string_name, scope);
+		if (EG(exception)) {
+			result = zend_exception_error(EG(exception), E_ERROR);
+		}
+	} zend_catch {
+		/* Bailing out abandons the frame without writing its compiled variables
+		 * back, so their slots in the table still point into it. */
+		zend_hash_clean(scope);
+	} zend_end_try();
+	return result;
+}
+
 zend_result libphp_eval_stringl_ex(const char *str, size_t str_len, zval *retval_ptr, const char *string_name, bool reset_global_ctx)
 {
+	zend_result result = FAILURE;
+
+	zend_try {
+		result = libphp_eval_stringl(str, str_len, retval_ptr, string_name, reset_global_ctx);
+		if (EG(exception)) {
+			result = zend_exception_error(EG(exception), E_ERROR);
+		}
+	} zend_end_try();
+	return result;
+}
+
+/* Call a function from outside of any script. Uncaught exceptions and fatal
+ * errors end the call instead of bailing out of the request. */
+zend_result libphp_call_function(zend_fcall_info *fci, zend_fcall_info_cache *fci_cache)
+{
+	zend_result result = FAILURE;
+
+	zend_try {
+		result = zend_call_function(fci, fci_cache);
+		if (EG(exception)) {
+			result = zend_exception_error(EG(exception), E_ERROR);
+		}
+	} zend_end_try();
+	return result;
+}
+
+/* The status the process would exit with under php-cli: the one passed to
+ * exit(), or 255 after a fatal error. */
+int libphp_exit_status(void)
+{
+	return EG(exit_status);
+}
+
+/* The exit status while exit() has not been called, see libphp_exit_watch. */
+#define LIBPHP_NO_EXIT INT_MIN
+
+/* Start detecting exit(), which only sets the exit status and unwinds the
+ * stack. Returns the current status, to pass to libphp_exited. */
+int libphp_exit_watch(void)
+{
+	int previous = EG(exit_status);
+
+	EG(exit_status) = LIBPHP_NO_EXIT;
+	return previous;
+}
+
+/* Check if the exit status was set since libphp_exit_watch, by exit() or a
+ * fatal error, and get it. Otherwise the previous status is restored. */
+bool libphp_exited(int previous, int *status)
+{
+	if (EG(exit_status) == LIBPHP_NO_EXIT) {
+		EG(exit_status) = previous;
+		return false;
+	}
+
+	*status = EG(exit_status);
+	return true;
+}
+
+/* The levels of the errors PHP currently reports, which the @ operator
+ * narrows while it is in effect. */
+int libphp_error_reporting(void)
+{
+	return EG(error_reporting);
+}
+
+/* Why the last fatal error was raised, see libphp_note_error. */
+static ZEND_TLS enum libphp_fatal_reason libphp_last_reason;
+
+/* Forget the last error, like error_clear_last(). */
+void libphp_clear_last_error(void)
+{
+	libphp_last_reason = LIBPHP_FATAL_ERROR;
+	PG(last_error_type) = 0;
+	PG(last_error_lineno) = 0;
+	if (PG(last_error_message)) {
+		zend_string_release(PG(last_error_message));
+		PG(last_error_message) = NULL;
+	}
+	if (PG(last_error_file)) {
+		zend_string_release(PG(last_error_file));
+		PG(last_error_file) = NULL;
+	}
+}
+
+/* Get the last error, like error_get_last(). The strings stay valid until the
+ * next error is raised. Returns false if there is none. */
+bool libphp_last_error(int *type, const char **file, size_t *file_len, uint32_t *lineno, const char **message, size_t *message_len)
+{
+	if (!PG(last_error_message)) {
+		return false;
+	}
+
+	*type = PG(last_error_type);
+	*file = PG(last_error_file) ? ZSTR_VAL(PG(last_error_file)) : NULL;
+	*file_len = PG(last_error_file) ? ZSTR_LEN(PG(last_error_file)) : 0;
+	*lineno = PG(last_error_lineno);
+	*message = ZSTR_VAL(PG(last_error_message));
+	*message_len = ZSTR_LEN(PG(last_error_message));
+	return true;
+}
+
+/* Limit the CPU time of the current request, like set_time_limit(), or lift
+ * the limit if `seconds` is 0. Returns the previous limit. */
+zend_long libphp_set_time_limit(zend_long seconds)
+{
+	zend_long previous = EG(timeout_seconds);
+
+	if (seconds > 0) {
+		zend_set_timeout(seconds, 0);
+	} else {
+		zend_unset_timeout();
+	}
+	EG(timeout_seconds) = seconds;
+	return previous;
+}
+
+/* Change memory_limit like ini_set() does, but without the warning PHP raises
+ * when more memory is already in use. */
+static bool libphp_alter_memory_limit(const char *value, size_t value_len)
+{
+	zend_string *key = zend_string_init("memory_limit", sizeof("memory_limit") - 1, 0);
+	int error_reporting = EG(error_reporting);
 	zend_result result;
 
-	result = libphp_eval_stringl(str, str_len, retval_ptr, string_name, reset_global_ctx);
-	if (EG(exception)) {
-		result = zend_exception_error(EG(exception), E_ERROR);
+	EG(error_reporting) = 0;
+	result = zend_alter_ini_entry_chars(key, value, value_len, ZEND_INI_SYSTEM, ZEND_INI_STAGE_RUNTIME);
+	EG(error_reporting) = error_reporting;
+
+	zend_string_release(key);
+	return result == SUCCESS;
+}
+
+/* Limit the memory of the current request through memory_limit, so ini_get()
+ * reports the limit too. Fails if more than `limit` bytes are already in use.
+ * Stores the previous setting in `previous`, for libphp_restore_memory_limit. */
+bool libphp_set_memory_limit(size_t limit, zend_string **previous)
+{
+	zend_ini_entry *ini_entry = zend_hash_str_find_ptr(EG(ini_directives), "memory_limit", sizeof("memory_limit") - 1);
+	char value[32];
+	int value_len = snprintf(value, sizeof(value), "%zu", limit);
+
+	*previous = ini_entry && ini_entry->value ? zend_string_copy(ini_entry->value) : NULL;
+
+	if (!libphp_alter_memory_limit(value, value_len)) {
+		if (*previous) {
+			zend_string_release(*previous);
+			*previous = NULL;
+		}
+		return false;
+	}
+	return true;
+}
+
+bool libphp_restore_memory_limit(zend_string *previous)
+{
+	bool result;
+
+	if (!previous) {
+		return true;
 	}
+
+	result = libphp_alter_memory_limit(ZSTR_VAL(previous), ZSTR_LEN(previous));
+	zend_string_release(previous);
 	return result;
 }
 
+/* Get the current value of an INI setting, like ini_get(). Returns false if
+ * there is no such setting; `value` is NULL if the setting has no value. */
+bool libphp_ini_get(const char *name, size_t name_len, const char **value, size_t *value_len)
+{
+	zend_ini_entry *ini_entry = zend_hash_str_find_ptr(EG(ini_directives), name, name_len);
+
+	if (!ini_entry) {
+		return false;
+	}
+
+	*value = ini_entry->value ? ZSTR_VAL(ini_entry->value) : NULL;
+	*value_len = ini_entry->value ? ZSTR_LEN(ini_entry->value) : 0;
+	return true;
+}
+
+/* Change an INI setting until the end of the request, like ini_set(). Unlike
+ * ini_set(), this can also change settings that are only allowed in php.ini. */
+bool libphp_ini_set(const char *name, size_t name_len, const char *value, size_t value_len)
+{
+	zend_string *key = zend_string_init(name, name_len, 0);
+	zend_result result = zend_alter_ini_entry_chars(key, value, value_len, ZEND_INI_SYSTEM, ZEND_INI_STAGE_RUNTIME);
+
+	zend_string_release(key);
+	return result == SUCCESS;
+}
+
+/* Set by libphp_vm_raise_interrupt for the VM of each thread. */
+static ZEND_TLS zend_atomic_bool libphp_interrupted;
+
+/* Set along with EG(timed_out), which the VM clears again right before it
+ * raises the timeout error. */
+static ZEND_TLS zend_atomic_bool libphp_timeout_raised;
+
+static void (*libphp_previous_interrupt_function)(zend_execute_data *execute_data);
+static void (*libphp_previous_on_timeout)(int seconds);
+
+static void libphp_interrupt_function(zend_execute_data *execute_data)
+{
+	if (libphp_previous_interrupt_function) {
+		libphp_previous_interrupt_function(execute_data);
+	}
+
+	if (zend_atomic_bool_exchange(&libphp_interrupted, false)) {
+		libphp_last_reason = LIBPHP_FATAL_INTERRUPTED;
+		zend_error_noreturn(E_ERROR, "Script execution was interrupted");
+	}
+}
+
+/* Called when max_execution_time runs out, from the signal handler that sets
+ * EG(timed_out). */
+static void libphp_on_timeout(int seconds)
+{
+	zend_atomic_bool_store(&libphp_timeout_raised, true);
+
+	if (libphp_previous_on_timeout) {
+		libphp_previous_on_timeout(seconds);
+	}
+}
+
+/* Chain our interrupt function and timeout hook in front of the ones PHP and
+ * extensions installed, like pcntl does. Must be called once, after the module
+ * has started up. */
+void libphp_install_vm_hooks(void)
+{
+	libphp_previous_interrupt_function = zend_interrupt_function;
+	zend_interrupt_function = libphp_interrupt_function;
+
+	libphp_previous_on_timeout = zend_on_timeout;
+	zend_on_timeout = libphp_on_timeout;
+}
+
+/* Record why the VM raised an error of `type`, before PHP handles it. User code
+ * can raise errors with any message, but not E_ERROR, which the VM uses for
+ * timeouts, exhausted memory and interrupts. */
+void libphp_note_error(int type)
+{
+	if ((type & E_ALL) != E_ERROR || libphp_last_reason != LIBPHP_FATAL_ERROR) {
+		return;
+	}
+
+	if (zend_alloc_in_memory_limit_error_reporting()) {
+		libphp_last_reason = LIBPHP_FATAL_OUT_OF_MEMORY;
+	} else if (!zend_atomic_bool_load(&EG(timed_out))
+		&& zend_atomic_bool_exchange(&libphp_timeout_raised, false)) {
+		libphp_last_reason = LIBPHP_FATAL_TIMEOUT;
+	}
+}
+
+/* Get why the last fatal error was raised, since libphp_clear_last_error. */
+enum libphp_fatal_reason libphp_last_fatal_reason(void)
+{
+	return libphp_last_reason;
+}
+
+/* The flags the VM of the current thread checks between instructions. */
+struct libphp_vm_flags libphp_vm_flags(void)
+{
+	struct libphp_vm_flags flags = { &EG(vm_interrupt), &EG(timed_out), &libphp_interrupted, &libphp_timeout_raised };
+
+	return flags;
+}
+
+/* Make the VM behind `flags` raise an "execution was interrupted" fatal error.
+ * Safe to call from any thread. */
+void libphp_vm_raise_interrupt(struct libphp_vm_flags flags)
+{
+	zend_atomic_bool_store(flags.interrupted, true);
+	zend_atomic_bool_store(flags.vm_interrupt, true);
+}
+
+void libphp_vm_clear_interrupt(struct libphp_vm_flags flags)
+{
+	zend_atomic_bool_store(flags.interrupted, false);
+}
+
+/* Make the VM behind `flags` raise a "Maximum execution time exceeded" fatal
+ * error. Safe to call from any thread. */
+void libphp_vm_raise_timeout(struct libphp_vm_flags flags)
+{
+	zend_atomic_bool_store(flags.timeout_raised, true);
+	zend_atomic_bool_store(flags.timed_out, true);
+	zend_atomic_bool_store(flags.vm_interrupt, true);
+}
+
+void libphp_vm_clear_timeout(struct libphp_vm_flags flags)
+{
+	zend_atomic_bool_store(flags.timed_out, false);
+	zend_atomic_bool_store(flags.timeout_raised, false);
+}
+
 bool libphp_execute_script_ex(zend_file_handle *primary_file, zval *retval, bool reset_global_ctx)
 {
 	zend_file_handle *prepend_file_p = NULL, *append_file_p = NULL;
@@ -313,7 +819,6 @@ int libphp_execute_simple_script(zend_file_handle *primary_file, zval *ret, bool
 	char *old_cwd;
 	ALLOCA_FLAG(use_heap)
 
-	EG(exit_status) = 0;
 #define OLD_CWD_SIZE 4096
 	old_cwd = do_alloca(OLD_CWD_SIZE, use_heap);
 	old_cwd[0] = '\0';
@@ -331,7 +836,7 @@ int libphp_execute_simple_script(zend_file_handle *primary_file, zval *ret, bool
 			php_ignore_value(VCWD_GETCWD(old_cwd, OLD_CWD_SIZE-1));
 			VCWD_CHDIR_FILE(ZSTR_VAL(primary_file->filename));
 		}
-		libphp_zend_execute_scripts(ZEND_REQUIRE, ret, reset_global_ctx, primary_file);
+		libphp_zend_execute_scripts(ZEND_REQUIRE, ret, 1, reset_global_ctx, primary_file);
 	} zend_end_try();
 
 	if (old_cwd[0] != '\0') {
@@ -342,6 +847,24 @@ int libphp_execute_simple_script(zend_file_handle *primary_file, zval *ret, bool
 	return EG(exit_status);
 }
 
+static zend_result libphp_zend_execute_op_array(zend_op_array *op_array, zval *retval, bool reset_global_ctx)
+{
+	zend_result ret = SUCCESS;
+
+	libphp_execute(op_array, retval, reset_global_ctx);
+	zend_exception_restore();
+	if (UNEXPECTED(EG(exception))) {
+		if (Z_TYPE(EG(user_exception_handler)) != IS_UNDEF) {
+			zend_user_exception_handler();
+		}
+		if (EG(exception)) {
+			ret = zend_exception_error(EG(exception), E_ERROR);
+		}
+	}
+
+	return ret;
+}
+
 zend_result libphp_zend_execute_script(int type, zval *retval, zend_file_handle *file_handle, bool reset_global_ctx)
 {
 	zend_op_array *op_array = zend_compile_file(file_handle, type);
@@ -351,16 +874,7 @@ zend_result libphp_zend_execute_script(int type, zval *retval, zend_file_handle
 
 	zend_result ret = SUCCESS;
 	if (op_array) {
-		libphp_execute(op_array, retval, reset_global_ctx);
-		zend_exception_restore();
-		if (UNEXPECTED(EG(exception))) {
-			if (Z_TYPE(EG(user_exception_handler)) != IS_UNDEF) {
-				zend_user_exception_handler();
-			}
-			if (EG(exception)) {
-				ret = zend_exception_error(EG(exception), E_ERROR);
-			}
-		}
+		ret = libphp_zend_execute_op_array(op_array, retval, reset_global_ctx);
 		zend_destroy_static_vars(op_array);
 		destroy_op_array(op_array);
 		efree_size(op_array, sizeof(zend_op_array));
@@ -371,6 +885,45 @@ zend_result libphp_zend_execute_script(int type, zval *retval, zend_file_handle
 	return ret;
 }
 
+/* Compile a complete PHP source held in memory as if it had been read from
+ * `filename`, so __FILE__, __DIR__ and error messages refer to that path. If
+ * `raw_code` is set the source does not start with an open tag. */
+zend_result libphp_zend_execute_source(const char *source, size_t source_len, const char *filename, bool raw_code, zval *retval, bool reset_global_ctx)
+{
+	zend_string *code_str = zend_string_init(source, source_len, 0);
+	zend_op_array *op_array = zend_compile_string(code_str, filename,
+		raw_code ? ZEND_COMPILE_POSITION_AFTER_OPEN_TAG : ZEND_COMPILE_POSITION_AT_SHEBANG);
+	zend_string_release(code_str);
+
+	zend_result ret = FAILURE;
+	if (op_array) {
+		ret = libphp_zend_execute_op_array(op_array, retval, reset_global_ctx);
+		zend_destroy_static_vars(op_array);
+		destroy_op_array(op_array);
+		efree_size(op_array, sizeof(zend_op_array));
+	}
+
+	return ret;
+}
+
+int libphp_execute_source(const char *source, size_t source_len, const char *filename, bool raw_code, zval *ret, bool reset_global_ctx)
+{
+
+	zend_try {
+		PG(during_request_startup) = 0;
+
+		libphp_zend_execute_source(source, source_len, filename, raw_code, ret, reset_global_ctx);
+	} zend_end_try();
+
+	if (EG(exception)) {
+		zend_try {
+			zend_exception_error(EG(exception), E_ERROR);
+		} zend_end_try();
+	}
+
+	return EG(exit_status);
+}
+
 zend_result libphp_zend_execute_scripts(int type, zval *retval, int file_count, bool reset_global_ctx, ...) /* {{{ */
 {
 	va_list files;
@@ -392,4 +945,148 @@ zend_result libphp_zend_execute_scripts(int type, zval *retval, int file_count,
 	va_end(files);
 
 	return ret;
-}
\ No newline at end of file
+}
+
+/* Compile a file once so it can be executed any number of times with
+ * libphp_execute_op_array. Returns NULL if the file does not compile. */
+zend_op_array *libphp_compile_file(const char *filename)
+{
+	zend_file_handle file_handle;
+	zend_op_array *op_array = NULL;
+
+	zend_stream_init_filename(&file_handle, filename);
+
+	zend_try {
+		op_array = zend_compile_file(&file_handle, ZEND_REQUIRE);
+		if (op_array && file_handle.opened_path) {
+			zend_hash_add_empty_element(&EG(included_files), file_handle.opened_path);
+		}
+	} zend_end_try();
+
+	zend_destroy_file_handle(&file_handle);
+
+	if (EG(exception)) {
+		zend_try {
+			zend_exception_error(EG(exception), E_ERROR);
+		} zend_end_try();
+	}
+
+	return op_array;
+}
+
+zend_op_array *libphp_compile_source(const char *source, size_t source_len, const char *filename, bool raw_code)
+{
+	zend_string *code_str = zend_string_init(source, source_len, 0);
+	zend_op_array *op_array = NULL;
+
+	zend_try {
+		op_array = zend_compile_string(code_str, filename,
+			raw_code ? ZEND_COMPILE_POSITION_AFTER_OPEN_TAG : ZEND_COMPILE_POSITION_AT_SHEBANG);
+	} zend_end_try();
+
+	zend_string_release(code_str);
+
+	if (EG(exception)) {
+		zend_try {
+			zend_exception_error(EG(exception), E_ERROR);
+		} zend_end_try();
+	}
+
+	return op_array;
+}
+
+int libphp_execute_op_array(zend_op_array *op_array, zval *ret, bool reset_global_ctx)
+{
+
+	zend_try {
+		PG(during_request_startup) = 0;
+
+		libphp_zend_execute_op_array(op_array, ret, reset_global_ctx);
+	} zend_end_try();
+
+	if (EG(exception)) {
+		zend_try {
+			zend_exception_error(EG(exception), E_ERROR);
+		} zend_end_try();
+	}
+
+	return EG(exit_status);
+}
+
+void libphp_destroy_op_array(zend_op_array *op_array)
+{
+	zend_destroy_static_vars(op_array);
+	destroy_op_array(op_array);
+	efree_size(op_array, sizeof(zend_op_array));
+}
+
+/* Drop the entries appended to a function or class table since it held
+ * `num_used` buckets, so compiling a file for linting declares nothing. */
+static void libphp_hash_truncate(HashTable *ht, uint32_t num_used)
+{
+	while (ht->nNumUsed > num_used) {
+		Bucket *p = ht->arData + ht->nNumUsed - 1;
+
+		if (Z_TYPE(p->val) == IS_UNDEF) {
+			break;
+		}
+		zend_hash_del_bucket(ht, p);
+	}
+}
+
+static zend_result libphp_lint(zend_file_handle *file_handle, zend_string *source, const char *filename, bool raw_code)
+{
+	zend_op_array *op_array = NULL;
+	zend_result ret = FAILURE;
+	uint32_t functions = CG(function_table)->nNumUsed;
+	uint32_t classes = CG(class_table)->nNumUsed;
+
+	zend_try {
+		if (file_handle) {
+			op_array = zend_compile_file(file_handle, ZEND_INCLUDE);
+		} else {
+			op_array = zend_compile_string(source, filename,
+				raw_code ? ZEND_COMPILE_POSITION_AFTER_OPEN_TAG : ZEND_COMPILE_POSITION_AT_SHEBANG);
+		}
+		if (op_array) {
+			destroy_op_array(op_array);
+			efree_size(op_array, sizeof(zend_op_array));
+			ret = SUCCESS;
+		}
+	} zend_end_try();
+
+	if (EG(exception)) {
+		zend_try {
+			zend_exception_error(EG(exception), E_ERROR);
+		} zend_end_try();
+		ret = FAILURE;
+	}
+
+	libphp_hash_truncate(CG(function_table), functions);
+	libphp_hash_truncate(CG(class_table), classes);
+
+	return ret;
+}
+
+/* Compile a file without executing it, like `php -l`. Diagnostics are raised
+ * through the usual error callback. */
+zend_result libphp_lint_file(const char *filename)
+{
+	zend_file_handle file_handle;
+	zend_result ret;
+
+	zend_stream_init_filename(&file_handle, filename);
+	ret = libphp_lint(&file_handle, NULL, NULL, false);
+	zend_destroy_file_handle(&file_handle);
+
+	return ret;
+}
+
+zend_result libphp_lint_source(const char *source, size_t source_len, const char *filename, bool raw_code)
+{
+	zend_string *code_str = zend_string_init(source, source_len, 0);
+	zend_result ret = libphp_lint(NULL, code_str, filename, raw_code);
+
+	zend_string_release(code_str);
+	return ret;
+}
@@ -9,15 +9,22 @@
 #include <ext/standard/php_var.h>
 #include "zend_smart_str.h"
 #include "main/php_variables.h"
+#include "main/php_output.h"
 
 uint8_t libphp_zval_get_type(const zval*);
 
 const char* libphp_zval_get_string(zval*);
+const char* libphp_zval_get_stringl(zval*, size_t *len);
 
 const char* libphp_var_export(zval *pz);
 
 void libphp_zval_create_string(zval *pz, const char *str);
 void libphp_zval_create_long(zval *pz, long l);
+void libphp_zval_create_null(zval *pz);
+void libphp_zval_create_bool(zval *pz, bool b);
+void libphp_zval_create_double(zval *pz, double d);
+void libphp_zval_create_stringl(zval *pz, const char *str, size_t len);
+HashTable *libphp_zval_create_array(zval *pz, uint32_t size);
 
 zend_string* libphp_zend_string_init();
 
@@ -25,10 +32,71 @@ void libphp_register_variable(const char *key, zval *value);
 
 void libphp_register_constant(const char *name, zval *value);
 
+HashTable *libphp_symbol_table(void);
+bool libphp_zval_copy_deref(zval *dst, zval *src);
+bool libphp_global_get(const char *name, size_t name_len, zval *retval);
+void libphp_global_set(const char *name, size_t name_len, zval *value);
+bool libphp_global_unset(const char *name, size_t name_len);
+void libphp_hash_str_update(HashTable *ht, const char *key, size_t key_len, zval *value);
+void libphp_hash_index_update(HashTable *ht, zend_long index, zval *value);
+
+int libphp_output_capture_start(void);
+void libphp_output_capture_end(int level, zval *contents);
+bool libphp_output_start_sink(size_t (*write)(void *data, const char *buf, size_t len), void (*dtor)(void *data), void *data);
+
 uint32_t libphp_zval_addref_p(zval* pz);
 uint32_t libphp_zval_delref_p(zval* pz);
 
+zend_result libphp_eval_stringl_scoped_ex(const char *str, size_t str_len, zval *retval_ptr, const char *string_name, HashTable *scope);
 zend_result libphp_eval_stringl_ex(const char *str, size_t str_len, zval *retval_ptr, const char *string_name, bool reset_global_ctx);
+zend_result libphp_call_function(zend_fcall_info *fci, zend_fcall_info_cache *fci_cache);
+
+int libphp_exit_status(void);
+int libphp_exit_watch(void);
+bool libphp_exited(int previous, int *status);
+int libphp_error_reporting(void);
+void libphp_clear_last_error(void);
+bool libphp_last_error(int *type, const char **file, size_t *file_len, uint32_t *lineno, const char **message, size_t *message_len);
+
+zend_long libphp_set_time_limit(zend_long seconds);
+bool libphp_set_memory_limit(size_t limit, zend_string **previous);
+bool libphp_restore_memory_limit(zend_string *previous);
+
+struct libphp_vm_flags {
+	zend_atomic_bool *vm_interrupt;
+	zend_atomic_bool *timed_out;
+	zend_atomic_bool *interrupted;
+	zend_atomic_bool *timeout_raised;
+};
+
+/* Why the VM raised the fatal error the code that ran last failed with. */
+enum libphp_fatal_reason {
+	/* The code raised the error itself. */
+	LIBPHP_FATAL_ERROR,
+	LIBPHP_FATAL_TIMEOUT,
+	LIBPHP_FATAL_OUT_OF_MEMORY,
+	LIBPHP_FATAL_INTERRUPTED,
+};
+
+struct libphp_vm_flags libphp_vm_flags(void);
+void libphp_vm_raise_timeout(struct libphp_vm_flags flags);
+void libphp_vm_clear_timeout(struct libphp_vm_flags flags);
+void libphp_install_vm_hooks(void);
+void libphp_note_error(int type);
+enum libphp_fatal_reason libphp_last_fatal_reason(void);
+
+bool libphp_ini_get(const char *name, size_t name_len, const char **value, size_t *value_len);
+bool libphp_ini_set(const char *name, size_t name_len, const char *value, size_t value_len);
+void libphp_vm_raise_interrupt(struct libphp_vm_flags flags);
+void libphp_vm_clear_interrupt(struct libphp_vm_flags flags);
 zend_result libphp_zend_execute_script(int type, zval *retval, zend_file_handle *file_handle, bool reset_global_ctx);
 zend_result libphp_zend_execute_scripts(int type, zval *retval, int file_count, bool reset_global_ctx, ...);
 int libphp_execute_simple_script(zend_file_handle *primary_file, zval *ret, bool reset_global_ctx);
+zend_result libphp_zend_execute_source(const char *source, size_t source_len, const char *filename, bool raw_code, zval *retval, bool reset_global_ctx);
+zend_op_array *libphp_compile_file(const char *filename);
+zend_op_array *libphp_compile_source(const char *source, size_t source_len, const char *filename, bool raw_code);
+int libphp_execute_op_array(zend_op_array *op_array, zval *ret, bool reset_global_ctx);
+void libphp_destroy_op_array(zend_op_array *op_array);
+zend_result libphp_lint_file(const char *filename);
+zend_result libphp_lint_source(const char *source, size_t source_len, const char *filename, bool raw_code);
+int libphp_execute_source(const char *source, size_t source_len, const char *filename, bool raw_code, zval *ret, bool reset_global_ctx);
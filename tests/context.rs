@@ -0,0 +1,36 @@
+//! Runs several contexts one after another on the same thread.
+
+use libphp::exec::Context;
+
+#[test]
+fn sequential_contexts_start_clean() {
+    for round in 1..=3 {
+        let mut context = Context::new();
+        context.on_init(move |ctx| ctx.define("ROUND", round));
+
+        // Nothing the previous context set, declared or printed is left over.
+        assert!(context.global("seen").is_none());
+        let (value, output) = context.capture_output(|ctx| {
+            ctx.result_of(
+                "defined('PREVIOUS') || function_exists('previous') || ob_get_level() > 1 \
+                 ? -1 : ROUND",
+                false,
+            )
+            .map(|value| value.to_int())
+        });
+        assert_eq!(value, Ok(round));
+        assert!(output.is_empty());
+
+        let (result, output) = context.capture_output(|ctx| {
+            ctx.execute_source(
+                "<?php $seen = true; define('PREVIOUS', true); function previous() {}\n\
+                 ob_start(); echo 'round ', ROUND;",
+                "round.php",
+            )
+            .map(drop)
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, format!("round {}", round).into_bytes());
+        assert!(context.global("seen").is_some());
+    }
+}
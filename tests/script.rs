@@ -0,0 +1,47 @@
+//! Runs compiled scripts in the requests of a context.
+
+use std::time::Duration;
+
+use libphp::{
+    error::PhpError,
+    exec::{Context, Limits},
+};
+
+#[test]
+fn runs_compiled_scripts_repeatedly() {
+    let mut context = Context::new();
+    context
+        .execute_source("<?php $runs = 0;", "setup.php")
+        .unwrap();
+
+    let script = context
+        .compile_source("<?php return ++$GLOBALS['runs'];", "count.php")
+        .unwrap();
+
+    assert_eq!(script.run(&mut context).unwrap().to_int(), 1);
+    assert_eq!(script.run(&mut context).unwrap().to_int(), 2);
+}
+
+#[test]
+fn does_not_run_scripts_of_ended_requests() {
+    let mut context = Context::new();
+    let script = context
+        .compile_source("<?php while (true) {}", "loop.php")
+        .unwrap();
+
+    let limits = Limits {
+        wall_time: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let result = context.with_limits(limits, |ctx| script.run(ctx));
+    assert_eq!(result.err(), Some(PhpError::Timeout));
+
+    // The timeout ended the request the script was compiled in, and freed it.
+    assert_eq!(script.run(&mut context).err(), Some(PhpError::Ended));
+    drop(script);
+
+    let script = context
+        .compile_source("<?php return 42;", "answer.php")
+        .unwrap();
+    assert_eq!(script.run(&mut context).unwrap().to_int(), 42);
+}
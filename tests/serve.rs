@@ -0,0 +1,346 @@
+//! Runs `libphp-serve` on a temporary document root and talks HTTP to it.
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+struct Server {
+    child: Child,
+    addr: SocketAddr,
+    root: PathBuf,
+}
+
+impl Server {
+    /// Serve a document root with a few scripts and files, and `args` on the command line.
+    fn start(name: &str, args: &[&str]) -> Self {
+        let root = env::temp_dir().join(format!("libphp-serve-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs")).unwrap();
+
+        fs::write(
+            root.join("index.php"),
+            "<?php echo 'index ', $_SERVER['PATH_INFO'] ?? '', ' ', $_SERVER['REQUEST_URI'];",
+        )
+        .unwrap();
+        fs::write(
+            root.join("echo.php"),
+            "<?php echo $_SERVER['REQUEST_METHOD'], ' ', file_get_contents('php://input');",
+        )
+        .unwrap();
+        fs::write(root.join("upper.PHP"), "<?php echo 'upper';").unwrap();
+        fs::write(root.join("hello.txt"), "Hello, world!\n").unwrap();
+        fs::write(root.join("docs/index.html"), "<p>Docs</p>").unwrap();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_libphp-serve"))
+            .args(["-l", "127.0.0.1:0", "-w", "2", "-q", "-t"])
+            .arg(&root)
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // The server logs the address it listens on once it is ready.
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let addr = loop {
+            let mut line = String::new();
+            assert!(stderr.read_line(&mut line).unwrap() > 0, "server exited");
+
+            if let Some((_, rest)) = line.split_once("http://") {
+                break rest.split_whitespace().next().unwrap().parse().unwrap();
+            }
+        };
+
+        Self { child, addr, root }
+    }
+
+    fn connect(&self) -> BufReader<TcpStream> {
+        let stream = TcpStream::connect(self.addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        BufReader::new(stream)
+    }
+
+    /// Send a raw request on a new connection and read the response.
+    fn send(&self, request: &str) -> Response {
+        let mut connection = self.connect();
+        connection.get_mut().write_all(request.as_bytes()).unwrap();
+        Response::read(&mut connection, request.starts_with("HEAD "))
+    }
+
+    fn get(&self, path: &str) -> Response {
+        self.send(&format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        ))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+fn read_line(reader: &mut impl BufRead) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    line.trim_end().to_string()
+}
+
+impl Response {
+    fn read(reader: &mut impl BufRead, head_only: bool) -> Self {
+        let status_line = read_line(reader);
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(reader);
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+
+        let mut response = Self {
+            status,
+            headers,
+            body: String::new(),
+        };
+        if head_only {
+            return response;
+        }
+
+        let mut body = Vec::new();
+        if response.header("Transfer-Encoding") == Some("chunked") {
+            loop {
+                let size = usize::from_str_radix(&read_line(reader), 16).unwrap();
+                let mut chunk = vec![0; size + 2];
+                reader.read_exact(&mut chunk).unwrap();
+                body.extend_from_slice(&chunk[..size]);
+
+                if size == 0 {
+                    break;
+                }
+            }
+        } else if let Some(length) = response.header("Content-Length") {
+            body.resize(length.parse().unwrap(), 0);
+            reader.read_exact(&mut body).unwrap();
+        } else {
+            reader.read_to_end(&mut body).unwrap();
+        }
+
+        response.body = String::from_utf8(body).unwrap();
+        response
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[test]
+fn routes_requests() {
+    let server = Server::start("routes", &[]);
+
+    let response = server.get("/");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, "index  /");
+
+    let response = server.get("/index.php/users/1?page=2");
+    assert_eq!(response.body, "index /users/1 /index.php/users/1?page=2");
+
+    // Scripts are run whatever the case of their extension, rather than shown.
+    assert_eq!(server.get("/upper.PHP").body, "upper");
+
+    assert_eq!(server.get("/docs/").body, "<p>Docs</p>");
+    assert_eq!(server.get("/missing").status, 404);
+    assert_eq!(server.get("/../Cargo.toml").status, 400);
+}
+
+#[test]
+fn routes_missing_files_to_the_router() {
+    let server = Server::start("router", &["index.php"]);
+
+    let response = server.get("/users/1");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, "index  /users/1");
+    assert_eq!(server.get("/hello.txt").body, "Hello, world!\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn does_not_follow_symlinks_out_of_the_root() {
+    let server = Server::start("symlinks", &[]);
+    let outside = server.root.with_extension("outside");
+
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(outside.join("secret.txt"), "secret").unwrap();
+    std::os::unix::fs::symlink(&outside, server.root.join("outside")).unwrap();
+
+    assert_eq!(server.get("/outside/secret.txt").status, 404);
+    fs::remove_dir_all(&outside).unwrap();
+}
+
+#[test]
+fn serves_static_files() {
+    let server = Server::start("static", &[]);
+
+    let response = server.get("/hello.txt");
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.header("Content-Type"),
+        Some("text/plain; charset=utf-8")
+    );
+    assert_eq!(response.header("Content-Length"), Some("14"));
+    assert!(response.header("Last-Modified").is_some());
+    assert_eq!(response.body, "Hello, world!\n");
+
+    let response = server.send("HEAD /hello.txt HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Length"), Some("14"));
+
+    let response = server.send("POST /hello.txt HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+    assert_eq!(response.status, 405);
+    assert_eq!(response.header("Allow"), Some("GET, HEAD"));
+}
+
+#[test]
+fn streams_script_output() {
+    let server = Server::start("stream", &[]);
+
+    let response = server.get("/echo.php");
+    assert_eq!(response.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(response.header("Content-Length"), None);
+    assert_eq!(response.body, "GET ");
+
+    // HTTP/1.0 clients don't understand chunks, so the body ends with the connection.
+    let response = server.send("GET /echo.php HTTP/1.0\r\n\r\n");
+    assert_eq!(response.header("Transfer-Encoding"), None);
+    assert_eq!(response.header("Connection"), Some("close"));
+    assert_eq!(response.body, "GET ");
+}
+
+#[test]
+fn passes_request_bodies() {
+    let server = Server::start("bodies", &[]);
+
+    let response = server.send(
+        "POST /echo.php HTTP/1.1\r\nContent-Length: 11\r\nConnection: close\r\n\r\nhello world",
+    );
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, "POST hello world");
+
+    let response = server.send(
+        "PUT /echo.php HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+         5\r\nhello\r\n6;name=value\r\n world\r\n0\r\nTrailer: yes\r\n\r\n",
+    );
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, "PUT hello world");
+}
+
+#[test]
+fn keeps_connections_alive() {
+    let server = Server::start("keep-alive", &[]);
+    let mut connection = server.connect();
+
+    for (request, body) in [
+        ("GET /hello.txt HTTP/1.1\r\n\r\n", "Hello, world!\n"),
+        (
+            "POST /echo.php HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc",
+            "POST abc",
+        ),
+        ("GET /hello.txt HTTP/1.1\r\n\r\n", "Hello, world!\n"),
+    ] {
+        connection.get_mut().write_all(request.as_bytes()).unwrap();
+
+        let response = Response::read(&mut connection, false);
+        assert_eq!(response.header("Connection"), Some("keep-alive"));
+        assert_eq!(response.body, body);
+    }
+
+    connection
+        .get_mut()
+        .write_all(b"GET /hello.txt HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let response = Response::read(&mut connection, false);
+    assert_eq!(response.header("Connection"), Some("close"));
+
+    // The server closes the connection after the response.
+    let mut rest = Vec::new();
+    connection.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn rejects_invalid_requests() {
+    let server = Server::start("invalid", &[]);
+
+    for request in [
+        "GET\r\n\r\n",
+        "GET hello.txt HTTP/1.1\r\n\r\n",
+        "GET /hello.txt HTTP/1.1\r\nNo colon\r\n\r\n",
+        "POST /echo.php HTTP/1.1\r\nContent-Length: abc\r\n\r\n",
+        "POST /echo.php HTTP/1.1\r\nContent-Length: +3\r\n\r\nabc",
+        "POST /echo.php HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nabc",
+        "POST /echo.php HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\nabcd",
+        "POST /echo.php HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n\
+         3\r\nabc\r\n0\r\n\r\n",
+        "POST /echo.php HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nxyz\r\n",
+    ] {
+        let response = server.send(request);
+        assert_eq!(response.status, 400, "{:?}", request);
+        assert_eq!(response.header("Connection"), Some("close"));
+    }
+
+    assert_eq!(server.send("GET / HTTP/2.0\r\n\r\n").status, 505);
+    assert_eq!(server.get("/%zz").status, 400);
+}
+
+#[test]
+fn rejects_large_requests() {
+    let server = Server::start("large", &[]);
+
+    let response = server.send(&format!(
+        "POST /echo.php HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+        64 * 1024 * 1024 + 1
+    ));
+    assert_eq!(response.status, 413);
+
+    let response = server.send(&format!(
+        "POST /echo.php HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n",
+        64 * 1024 * 1024 + 1
+    ));
+    assert_eq!(response.status, 413);
+
+    // A chunk size that overflows the body length is too large as well.
+    let response = server.send(
+        "POST /echo.php HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+         1\r\na\r\nffffffffffffffff\r\n",
+    );
+    assert_eq!(response.status, 413);
+
+    let response = server.send(&format!(
+        "GET / HTTP/1.1\r\nX-Large: {}\r\n\r\n",
+        "a".repeat(64 * 1024)
+    ));
+    assert_eq!(response.status, 431);
+}